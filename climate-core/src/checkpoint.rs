@@ -0,0 +1,225 @@
+//! Versioned on-disk snapshots of a [`Model`].
+//!
+//! A checkpoint is the magic bytes, a little-endian format version, the size
+//! in bytes of the model's float type and then the bincode-encoded model,
+//! config included. Floats are stored bit for bit, so a restarted run
+//! continues exactly like an uninterrupted one, and a checkpoint only loads
+//! into a build of the same precision.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem,
+    path::Path,
+};
+
+use crate::{Model, config::ConfigError, float::Float, grid::Grid};
+
+const MAGIC: &[u8; 8] = b"CLIMCKPT";
+pub const VERSION: u32 = 13;
+/// Size of the float type this build stores
+const PRECISION: u8 = mem::size_of::<Float>() as u8;
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    NotACheckpoint,
+    UnsupportedVersion(u32),
+    PrecisionMismatch(u8),
+    Encoding(bincode::Error),
+    Config(ConfigError),
+    GridMismatch { expected: Grid, found: Grid },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "checkpoint I/O failed: {err}"),
+            CheckpointError::NotACheckpoint => write!(f, "not a checkpoint file"),
+            CheckpointError::UnsupportedVersion(version) => write!(
+                f,
+                "checkpoint format version {version} is not supported (expected {VERSION})"
+            ),
+            CheckpointError::PrecisionMismatch(size) => write!(
+                f,
+                "checkpoint holds {}-bit floats but this build uses {}-bit floats",
+                8 * size,
+                8 * PRECISION
+            ),
+            CheckpointError::Encoding(err) => write!(f, "corrupt checkpoint: {err}"),
+            CheckpointError::Config(err) => write!(f, "checkpoint config: {err}"),
+            CheckpointError::GridMismatch { expected, found } => write!(
+                f,
+                "temperature field is {}x{} but the config says {}x{}",
+                found.width, found.height, expected.width, expected.height
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CheckpointError::Io(err) => Some(err),
+            CheckpointError::Encoding(err) => Some(err),
+            CheckpointError::Config(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<bincode::Error> for CheckpointError {
+    fn from(err: bincode::Error) -> Self {
+        CheckpointError::Encoding(err)
+    }
+}
+
+pub fn write(model: &Model, mut writer: impl Write) -> Result<(), CheckpointError> {
+    writer.write_all(MAGIC)?;
+    writer.write_all(&VERSION.to_le_bytes())?;
+    writer.write_all(&[PRECISION])?;
+    bincode::serialize_into(&mut writer, model)?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read(mut reader: impl Read) -> Result<Model, CheckpointError> {
+    let mut magic = [0; 8];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|_| CheckpointError::NotACheckpoint)?;
+    if &magic != MAGIC {
+        return Err(CheckpointError::NotACheckpoint);
+    }
+
+    let mut version = [0; 4];
+    reader.read_exact(&mut version)?;
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        return Err(CheckpointError::UnsupportedVersion(version));
+    }
+
+    let mut precision = [0; 1];
+    reader.read_exact(&mut precision)?;
+    if precision[0] != PRECISION {
+        return Err(CheckpointError::PrecisionMismatch(precision[0]));
+    }
+
+    let model: Model = bincode::deserialize_from(reader)?;
+    model.config().validate().map_err(CheckpointError::Config)?;
+    let expected = model.config().grid;
+    let found = model.temperature().grid();
+    if found != expected {
+        return Err(CheckpointError::GridMismatch { expected, found });
+    }
+    Ok(model)
+}
+
+pub fn save(model: &Model, path: impl AsRef<Path>) -> Result<(), CheckpointError> {
+    write(model, BufWriter::new(File::create(path)?))
+}
+
+pub fn load(path: impl AsRef<Path>) -> Result<Model, CheckpointError> {
+    read(BufReader::new(File::open(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::{AdaptiveConfig, AtmosphereConfig, MoonConfig, SimConfig},
+        integrator::Method,
+    };
+
+    /// A small run that carries every kind of state across a restart: the
+    /// step control of adaptive diffusion, an atmosphere layer and a moon.
+    fn config() -> SimConfig {
+        let mut config = SimConfig {
+            grid: Grid {
+                width: 32,
+                height: 12,
+            },
+            atmosphere: Some(AtmosphereConfig::default()),
+            moons: vec![MoonConfig::default()],
+            ..SimConfig::default()
+        };
+        config.initial.seed = Some(3);
+        config.diffusion.scheme = Method::Rk45;
+        config.diffusion.adaptive = Some(AdaptiveConfig::default());
+        config
+    }
+
+    #[test]
+    fn restart_continues_bit_for_bit() {
+        let mut model = Model::new(config());
+        let dt = model.config().planet_dt();
+        for _ in 0..40 {
+            model.step(dt);
+        }
+
+        let mut bytes = Vec::new();
+        write(&model, &mut bytes).unwrap();
+        let mut restarted = read(bytes.as_slice()).unwrap();
+        for _ in 0..40 {
+            model.step(dt);
+            restarted.step(dt);
+        }
+
+        assert_eq!(restarted.time().to_bits(), model.time().to_bits());
+        let bits = |temp: &crate::TempMap| temp.field().mapv(Float::to_bits);
+        assert_eq!(bits(restarted.temperature()), bits(model.temperature()));
+        assert_eq!(
+            bits(restarted.atmosphere_temperature().unwrap()),
+            bits(model.atmosphere_temperature().unwrap())
+        );
+        assert_eq!(
+            bits(restarted.moon_temperature(0)),
+            bits(model.moon_temperature(0))
+        );
+        let (stats, restarted_stats) = (
+            model.diffusion_stats().unwrap(),
+            restarted.diffusion_stats().unwrap(),
+        );
+        assert_eq!(restarted_stats.accepted, stats.accepted);
+        assert_eq!(
+            restarted_stats.last_step.to_bits(),
+            stats.last_step.to_bits()
+        );
+    }
+
+    #[test]
+    fn rejects_other_files_and_versions() {
+        assert!(matches!(
+            read(&b"not a checkpoint"[..]),
+            Err(CheckpointError::NotACheckpoint)
+        ));
+
+        let mut bytes = Vec::new();
+        write(&Model::new(config()), &mut bytes).unwrap();
+        bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        assert!(matches!(
+            read(bytes.as_slice()),
+            Err(CheckpointError::UnsupportedVersion(version)) if version == VERSION - 1
+        ));
+    }
+
+    #[test]
+    fn rejects_the_other_precision() {
+        let mut bytes = Vec::new();
+        write(&Model::new(config()), &mut bytes).unwrap();
+        // 4 for 8 and 8 for 4
+        let other = 12 - PRECISION;
+        bytes[MAGIC.len() + 4] = other;
+        assert!(matches!(
+            read(bytes.as_slice()),
+            Err(CheckpointError::PrecisionMismatch(size)) if size == other
+        ));
+    }
+}
@@ -0,0 +1,1108 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    coupling::{Process, Splitting},
+    float::{Float, Quat, consts::PI},
+    forcing::{self, Cycle, Element, Sample},
+    grid::Grid,
+    integrator::Method,
+    orbit::KeplerSolver,
+};
+
+/// Every physical and numerical parameter of a run.
+///
+/// Missing fields fall back to the defaults below, so a config file only
+/// has to list what it changes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimConfig {
+    pub simulation: SimulationConfig,
+    pub coupling: CouplingConfig,
+    pub grid: Grid,
+    pub initial: InitialConfig,
+    /// Every star in the system, indexed from 0 in this order
+    pub stars: Vec<StarConfig>,
+    pub orbit: OrbitConfig,
+    pub planet: PlanetConfig,
+    /// Natural satellites of the planet, each with a climate of its own
+    pub moons: Vec<MoonConfig>,
+    /// Several planets run side by side, each with a model of its own; left
+    /// empty, `orbit`, `planet` and `moons` describe the only one
+    pub planets: Vec<SystemPlanet>,
+    /// Set on the config of one planet of several, see
+    /// [`SimConfig::planet_configs`]
+    pub system: Option<SystemMember>,
+    pub diffusion: DiffusionConfig,
+    pub flux: FluxConfig,
+    pub radiation: RadiationConfig,
+    /// A gray atmosphere layer of the planet with a temperature of its own;
+    /// without one, and on the moons, the surface radiates straight to space
+    pub atmosphere: Option<AtmosphereConfig>,
+    pub forcing: ForcingConfig,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            simulation: SimulationConfig::default(),
+            coupling: CouplingConfig::default(),
+            grid: Grid::default(),
+            initial: InitialConfig::default(),
+            stars: vec![StarConfig::default()],
+            orbit: OrbitConfig::default(),
+            planet: PlanetConfig::default(),
+            moons: Vec::new(),
+            planets: Vec::new(),
+            system: None,
+            diffusion: DiffusionConfig::default(),
+            flux: FluxConfig::default(),
+            radiation: RadiationConfig::default(),
+            atmosphere: None,
+            forcing: ForcingConfig::default(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SimulationConfig {
+    /// Length of one fixed physics tick in seconds
+    pub update_rate: f64,
+    /// Run time before plotting and exiting, in seconds
+    pub max_time: f32,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        SimulationConfig {
+            update_rate: 0.01,
+            max_time: 5. * 60., // Run for 5 minutes
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CouplingConfig {
+    pub splitting: Splitting,
+    /// Order the processes run in when split; each appears exactly once
+    pub order: Vec<Process>,
+}
+
+impl Default for CouplingConfig {
+    fn default() -> Self {
+        CouplingConfig {
+            splitting: Splitting::Lie,
+            order: Process::ALL.to_vec(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InitialConfig {
+    /// Seed for a random perturbation of the initial temperature field;
+    /// without one every cell starts at 0
+    pub seed: Option<u64>,
+    /// Largest perturbation added to a cell when seeded
+    pub perturbation: Float,
+}
+
+impl Default for InitialConfig {
+    fn default() -> Self {
+        InitialConfig {
+            seed: None,
+            perturbation: 1.,
+        }
+    }
+}
+
+/// One star of the system.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StarConfig {
+    /// Power output; the irradiance at distance `d` is `luminosity / (4π d²)`
+    pub luminosity: Float,
+    /// Radius, in the units of the orbit
+    pub radius: Float,
+    /// Brightening of the star as it ages
+    pub evolution: Option<StarEvolution>,
+    /// Orbit around the system's barycenter at the origin, or around an
+    /// earlier star; without one the star sits at the origin
+    pub orbit: Option<OrbitConfig>,
+}
+
+/// Main-sequence brightening after Gough (1981),
+/// `L(age) = luminosity / (1 + 2/5 (1 - age / reference_age))`, which makes a
+/// young star about 30 % fainter: the faint young Sun. The formula only
+/// holds on the main sequence and diverges at 3.5 reference ages, so past
+/// [`StarEvolution::MAX_AGE`] reference ages the star stays as bright as it
+/// is then.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StarEvolution {
+    /// Age at the start of the run, in forcing years
+    pub age: Float,
+    /// Age at which the star shines with `luminosity`, in forcing years
+    pub reference_age: Float,
+}
+
+impl StarEvolution {
+    /// End of the main sequence in reference ages, about 10 billion years
+    /// for the Sun
+    pub const MAX_AGE: Float = 2.2;
+}
+
+impl Default for StarConfig {
+    fn default() -> Self {
+        StarConfig {
+            // An irradiance of 0.1 at the default semi-major axis
+            luminosity: 40. * PI,
+            radius: 0.05,
+            evolution: None,
+            orbit: None,
+        }
+    }
+}
+
+impl StarConfig {
+    /// Luminosity after `years` forcing years.
+    pub fn luminosity(&self, years: Float) -> Float {
+        match &self.evolution {
+            Some(evolution) => {
+                let age =
+                    (evolution.age + years).min(StarEvolution::MAX_AGE * evolution.reference_age);
+                self.luminosity / (1. + 0.4 * (1. - age / evolution.reference_age))
+            }
+            None => self.luminosity,
+        }
+    }
+
+    /// Irradiance at `distance` from the star after `years` forcing years.
+    pub fn irradiance(&self, distance: Float, years: Float) -> Float {
+        self.luminosity(years) / (4. * PI * distance.powi(2))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrbitConfig {
+    pub period_time: Float,
+    /// Time of perihelion passage
+    pub per_time: Float,
+    /// Eccentricity
+    pub e: Float,
+    /// Semi-major axis
+    pub a: Float,
+    /// Tilt of the orbital plane against the reference plane, in degrees
+    pub inclination: Float,
+    /// Longitude of the ascending node from the reference direction, in
+    /// degrees
+    pub ascending_node: Float,
+    /// Angle from the ascending node to periapsis in the orbital plane, in
+    /// degrees
+    pub arg_periapsis: Float,
+    /// How the eccentric anomaly is found
+    pub solver: KeplerSolver,
+    /// Largest residual of Kepler's equation the iterative solvers accept
+    pub tolerance: Float,
+    /// Index of the star this orbit goes around; without one it goes around
+    /// the origin
+    pub host: Option<usize>,
+}
+
+impl Default for OrbitConfig {
+    fn default() -> Self {
+        OrbitConfig {
+            period_time: 60., // Let one year be one minute
+            per_time: 0.,
+            e: 0.16,
+            a: 10.,
+            inclination: 0.,
+            ascending_node: 0.,
+            arg_periapsis: 0.,
+            solver: KeplerSolver::Halley,
+            tolerance: 1e-6,
+            host: None,
+        }
+    }
+}
+
+impl OrbitConfig {
+    /// Mean motion
+    pub fn n(&self) -> Float {
+        2. * PI / self.period_time
+    }
+
+    /// Rotation from the perifocal frame, with periapsis on +x and the orbit
+    /// running counterclockwise in the xy-plane, into the frame of the system.
+    pub fn frame(&self) -> Quat {
+        Quat::from_rotation_z(self.ascending_node.to_radians())
+            * Quat::from_rotation_x(self.inclination.to_radians())
+            * Quat::from_rotation_z(self.arg_periapsis.to_radians())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlanetConfig {
+    /// Radius, in the units of the orbit; it sets the shadows the planet
+    /// casts and receives
+    pub radius: Float,
+    /// Angle between the spin axis and the orbit normal, in degrees
+    #[serde(alias = "tilt")]
+    pub obliquity: Float,
+    /// Direction the north pole leans towards, in degrees from periapsis in
+    /// the direction of motion; 0 puts northern winter at periapsis and 180
+    /// northern summer
+    pub axis_azimuth: Float,
+    /// Sidereal rotation period in seconds
+    pub rotation_period: Float,
+    pub spin: Spin,
+}
+
+/// Sense of the planet's rotation about its north pole.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Spin {
+    /// Counterclockwise seen from above the north pole, like the orbit
+    Prograde,
+    Retrograde,
+}
+
+impl Default for PlanetConfig {
+    fn default() -> Self {
+        PlanetConfig {
+            // The size the planet is drawn at
+            radius: 0.5,
+            obliquity: 23.,
+            axis_azimuth: 0.,
+            rotation_period: 4. * PI,
+            // The sense the planet has always turned in, which the default
+            // parameters are calibrated for
+            spin: Spin::Retrograde,
+        }
+    }
+}
+
+/// One planet of a system with several. Whatever it leaves out it shares
+/// with the top-level `orbit`, `planet` and `moons`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SystemPlanet {
+    /// Shown in the HUD and used to name its output files
+    pub name: String,
+    pub orbit: Option<OrbitConfig>,
+    pub planet: Option<PlanetConfig>,
+    pub moons: Option<Vec<MoonConfig>>,
+}
+
+/// Where a planet's config came from when it is one of several. The clock
+/// of the whole system, forcing time and the age of the stars, runs in years
+/// of the top-level orbit, so every planet sees the same stars at the same
+/// time.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SystemMember {
+    /// The planet's name in `planets`
+    pub name: String,
+    /// Names of every planet of the system, in order
+    pub planets: Vec<String>,
+    /// Length of the system's year in simulated seconds
+    pub year: Float,
+}
+
+/// A moon of the planet. It runs the same energy balance on a grid like the
+/// planet's, lit by the same stars.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MoonConfig {
+    /// Orbit around the planet, oriented in the frame of the system
+    pub orbit: OrbitConfig,
+    /// Size, spin axis and day of the moon
+    pub body: PlanetConfig,
+}
+
+impl Default for MoonConfig {
+    fn default() -> Self {
+        MoonConfig {
+            orbit: OrbitConfig {
+                period_time: 3.,
+                e: 0.,
+                a: 1.5,
+                ..OrbitConfig::default()
+            },
+            // Tidally locked, always showing the planet the same face
+            body: PlanetConfig {
+                radius: 0.15,
+                obliquity: 0.,
+                axis_azimuth: 0.,
+                rotation_period: 3.,
+                spin: Spin::Prograde,
+            },
+        }
+    }
+}
+
+impl PlanetConfig {
+    /// Orientation of the planet before it has turned at all: the north pole
+    /// tilted by the obliquity from the orbit normal towards the azimuth.
+    pub fn axis(&self, orbit: &OrbitConfig) -> Quat {
+        orbit.frame()
+            * Quat::from_rotation_z(self.axis_azimuth.to_radians())
+            * Quat::from_rotation_y(self.obliquity.to_radians())
+    }
+
+    /// Angle the planet has turned about its north pole after `time`
+    /// seconds. Only the fraction of the current turn is kept, so the angle
+    /// stays accurate however long the run.
+    pub fn spin_angle(&self, time: Float) -> Float {
+        let turns = (time / self.rotation_period).rem_euclid(1.);
+        match self.spin {
+            Spin::Prograde => 2. * PI * turns,
+            Spin::Retrograde => -2. * PI * turns,
+        }
+    }
+
+    /// Length of the solar day, the time between two noons, on an orbit of
+    /// period `year`.
+    pub fn solar_day(&self, year: Float) -> Float {
+        let orbit_rate = match self.spin {
+            Spin::Prograde => 1. / year,
+            Spin::Retrograde => -1. / year,
+        };
+        1. / (1. / self.rotation_period - orbit_rate)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DiffusionConfig {
+    /// Diffusivity on the unit sphere, per simulated second
+    pub kappa: Float,
+    /// Safety factor on the explicit step; unused by the implicit methods
+    /// and in adaptive mode
+    pub cfl: Float,
+    pub scheme: Method,
+    /// Choose the steps from the error estimate of an embedded scheme
+    /// instead of from `cfl`
+    pub adaptive: Option<AdaptiveConfig>,
+}
+
+impl Default for DiffusionConfig {
+    fn default() -> Self {
+        DiffusionConfig {
+            kappa: 8e-4,
+            cfl: 0.01,
+            scheme: Method::Rk4,
+            adaptive: None,
+        }
+    }
+}
+
+impl DiffusionConfig {
+    /// Largest explicit step in simulated seconds.
+    pub fn dt(&self, grid: &Grid) -> Float {
+        self.cfl * grid.dcolat() * grid.dlon() / (self.kappa * 20.)
+    }
+
+    /// Number of equal steps diffusion takes to cover `dt` when not adaptive.
+    pub fn steps(&self, grid: &Grid, dt: Float) -> u32 {
+        if self.scheme.is_implicit() {
+            1
+        } else {
+            ((dt / self.dt(grid)).ceil() as u32).max(1)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdaptiveConfig {
+    /// Largest local error accepted per step, in temperature units
+    pub tolerance: Float,
+    /// Fraction of the optimal step size actually tried
+    pub safety: Float,
+    /// Bounds on how much the step may change after one attempt
+    pub max_growth: Float,
+    pub min_shrink: Float,
+}
+
+impl Default for AdaptiveConfig {
+    fn default() -> Self {
+        AdaptiveConfig {
+            tolerance: 1e-4,
+            safety: 0.9,
+            max_growth: 5.,
+            min_shrink: 0.2,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FluxConfig {
+    pub rho: Float,
+    pub r: Float,
+    /// spridning/(spridning + absorbtion)
+    pub omega: Float,
+    /// Optical depth
+    pub tau: Float,
+    /// Airmass
+    pub m_eff: Float,
+    /// Samples of the transmission curve over `mu` to interpolate between;
+    /// 0 evaluates it exactly for every cell
+    pub transmission_table: usize,
+}
+
+impl Default for FluxConfig {
+    fn default() -> Self {
+        FluxConfig {
+            rho: 0.05,
+            r: 0.08,
+            omega: 0.98,
+            tau: 0.3,
+            m_eff: 1.7,
+            transmission_table: 0,
+        }
+    }
+}
+
+impl FluxConfig {
+    pub fn c_diff(&self) -> Float {
+        self.omega * self.tau * self.m_eff / 2.
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RadiationConfig {
+    pub eps: Float,
+    pub sigma: Float,
+    pub c: Float,
+    pub scheme: Method,
+}
+
+impl Default for RadiationConfig {
+    fn default() -> Self {
+        RadiationConfig {
+            eps: 1.,
+            sigma: 1e-7,
+            c: 1.,
+            scheme: Method::Euler,
+        }
+    }
+}
+
+/// A single gray layer above the surface. It lets sunlight through, absorbs
+/// the fraction `eps` of the surface's longwave emission and radiates with
+/// the same emissivity as much up to space as down to the surface, which
+/// warms the surface: the greenhouse effect. The layer only exchanges heat
+/// vertically, there is no transport between its cells.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AtmosphereConfig {
+    /// Longwave absorptivity and emissivity of the layer
+    pub eps: Float,
+    /// Heat capacity of the layer, in the units of `radiation.c`
+    pub c: Float,
+}
+
+impl Default for AtmosphereConfig {
+    fn default() -> Self {
+        AtmosphereConfig { eps: 0.8, c: 0.5 }
+    }
+}
+
+/// Long-term variation of the orbit and the spin axis, see [`forcing`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ForcingConfig {
+    /// Forcing years that pass per year of the system, see
+    /// [`SimConfig::year`]
+    pub acceleration: Float,
+    /// Forcing years for the spin axis to precess a full turn, negative
+    /// against the orbital motion
+    pub precession_period: Option<Float>,
+    /// Periodic variations added to the elements
+    pub cycles: Vec<Cycle>,
+    /// CSV file of `time,eccentricity,obliquity,axis_azimuth` rows, read into
+    /// `series` when the config is loaded
+    pub table: Option<PathBuf>,
+    /// Elements over forcing time, replacing the configured ones
+    pub series: Vec<Sample>,
+}
+
+impl Default for ForcingConfig {
+    fn default() -> Self {
+        ForcingConfig {
+            acceleration: 1.,
+            precession_period: None,
+            cycles: Vec::new(),
+            table: None,
+            series: Vec::new(),
+        }
+    }
+}
+
+impl ForcingConfig {
+    pub fn is_active(&self) -> bool {
+        self.precession_period.is_some() || !self.cycles.is_empty() || !self.series.is_empty()
+    }
+
+    /// Forcing years after `time` simulated seconds in years of `year`
+    /// seconds.
+    pub fn years(&self, time: Float, year: Float) -> Float {
+        self.acceleration * time / year
+    }
+
+    /// Vary the elements of `orbit` and `planet` to their values at forcing
+    /// year `years`: the series replaces them, then the cycles and the
+    /// precession are added on top.
+    pub fn apply(&self, years: Float, orbit: &mut OrbitConfig, planet: &mut PlanetConfig) {
+        if !self.series.is_empty() {
+            let sample = forcing::interpolate(&self.series, years);
+            orbit.e = sample.eccentricity;
+            planet.obliquity = sample.obliquity;
+            planet.axis_azimuth = sample.axis_azimuth;
+        }
+        for cycle in &self.cycles {
+            let value = cycle.value(years);
+            match cycle.element {
+                Element::Eccentricity => orbit.e += value,
+                Element::Obliquity => planet.obliquity += value,
+                Element::AxisAzimuth => planet.axis_azimuth += value,
+            }
+        }
+        if let Some(period) = self.precession_period {
+            planet.axis_azimuth += 360. * years / period;
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Invalid {
+        field: &'static str,
+        reason: String,
+    },
+    /// A problem with one of several planets
+    Planet {
+        name: String,
+        source: Box<ConfigError>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(err) => write!(f, "could not read config: {err}"),
+            ConfigError::Parse(err) => write!(f, "could not parse config: {err}"),
+            ConfigError::Invalid { field, reason } => write!(f, "invalid `{field}`: {reason}"),
+            ConfigError::Planet { name, source } => write!(f, "planet `{name}`: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(err) => Some(err),
+            ConfigError::Parse(err) => Some(err),
+            ConfigError::Invalid { .. } => None,
+            ConfigError::Planet { source, .. } => Some(source),
+        }
+    }
+}
+
+impl SimConfig {
+    /// Read and validate a TOML config file. Files it names are looked up
+    /// next to it.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(ConfigError::Io)?;
+        Self::parse(&text, path.parent().unwrap_or(Path::new("")))
+    }
+
+    /// Parse and validate a TOML config. Files it names are looked up
+    /// relative to the working directory.
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Self::parse(text, Path::new(""))
+    }
+
+    fn parse(text: &str, dir: &Path) -> Result<Self, ConfigError> {
+        let mut config: SimConfig = toml::from_str(text).map_err(ConfigError::Parse)?;
+        // Read the table in so the config, and any checkpoint holding it,
+        // stands on its own
+        if let Some(table) = config.forcing.table.take() {
+            config.forcing.series = forcing::read_series(&dir.join(table))?;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn to_toml(&self) -> String {
+        toml::to_string_pretty(self).expect("config is always representable as TOML")
+    }
+
+    /// Every planet of the system with a config of its own, in which
+    /// `orbit`, `planet` and `moons` are its own, `planets` is empty and
+    /// `system` keeps its name and the year of the top-level orbit.
+    pub fn planet_configs(&self) -> Vec<(String, SimConfig)> {
+        if self.planets.is_empty() {
+            return vec![(self.name().to_string(), self.clone())];
+        }
+        self.planets
+            .iter()
+            .map(|entry| {
+                let mut config = self.clone();
+                config.planets = Vec::new();
+                config.system = Some(SystemMember {
+                    name: entry.name.clone(),
+                    planets: self
+                        .planets
+                        .iter()
+                        .map(|entry| entry.name.clone())
+                        .collect(),
+                    year: self.orbit.period_time,
+                });
+                if let Some(orbit) = &entry.orbit {
+                    config.orbit = orbit.clone();
+                }
+                if let Some(planet) = &entry.planet {
+                    config.planet = planet.clone();
+                }
+                if let Some(moons) = &entry.moons {
+                    config.moons = moons.clone();
+                }
+                (entry.name.clone(), config)
+            })
+            .collect()
+    }
+
+    /// Name of the planet, `Planet` unless it is one of several.
+    pub fn name(&self) -> &str {
+        self.system
+            .as_ref()
+            .map_or("Planet", |system| system.name.as_str())
+    }
+
+    /// Length of the year the system's clock runs in: the planet's orbit,
+    /// or the top-level orbit for one planet of several.
+    pub fn year(&self) -> Float {
+        self.system
+            .as_ref()
+            .map_or(self.orbit.period_time, |system| system.year)
+    }
+
+    pub fn diffusion_dt(&self) -> Float {
+        self.diffusion.dt(&self.grid)
+    }
+
+    /// Diffusion steps per physics tick.
+    pub fn diffusion_steps(&self) -> u32 {
+        self.diffusion.steps(&self.grid, self.planet_dt())
+    }
+
+    /// Length of one physics tick in simulated seconds.
+    pub fn planet_dt(&self) -> Float {
+        self.simulation.update_rate as Float
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let SimConfig {
+            simulation,
+            coupling,
+            grid,
+            initial,
+            stars,
+            orbit,
+            planet,
+            moons,
+            planets,
+            system,
+            diffusion,
+            flux,
+            radiation,
+            atmosphere,
+            forcing,
+        } = self;
+
+        positive("simulation.update_rate", simulation.update_rate as Float)?;
+        positive("simulation.max_time", simulation.max_time as Float)?;
+
+        check(
+            "coupling.order",
+            coupling.order.len() == Process::ALL.len()
+                && Process::ALL.iter().all(|p| coupling.order.contains(p)),
+            "must list diffusion, insolation and radiation once each",
+        )?;
+        check(
+            "coupling.splitting",
+            coupling.splitting != Splitting::Coupled || !diffusion.scheme.is_implicit(),
+            "coupled stepping needs an explicit diffusion.scheme",
+        )?;
+
+        check("grid.width", grid.width >= 3, "must be at least 3")?;
+        check("grid.height", grid.height >= 3, "must be at least 3")?;
+
+        non_negative("initial.perturbation", initial.perturbation)?;
+
+        check("stars", !stars.is_empty(), "must list at least one star")?;
+        // Farthest any part of each star gets from the origin
+        let mut reach: Vec<Float> = Vec::with_capacity(stars.len());
+        for (i, star) in stars.iter().enumerate() {
+            non_negative("stars.luminosity", star.luminosity)?;
+            positive("stars.radius", star.radius)?;
+            if let Some(evolution) = &star.evolution {
+                non_negative("stars.evolution.age", evolution.age)?;
+                positive("stars.evolution.reference_age", evolution.reference_age)?;
+                check(
+                    "stars.evolution.age",
+                    evolution.age <= StarEvolution::MAX_AGE * evolution.reference_age,
+                    "must be on the main sequence, at most 2.2 times `reference_age`",
+                )?;
+            }
+            let mut extent = star.radius;
+            if let Some(orbit) = &star.orbit {
+                check_orbit(
+                    orbit,
+                    [
+                        "stars.orbit.period_time",
+                        "stars.orbit.per_time",
+                        "stars.orbit.e",
+                        "stars.orbit.a",
+                        "stars.orbit.inclination",
+                        "stars.orbit.ascending_node",
+                        "stars.orbit.arg_periapsis",
+                        "stars.orbit.tolerance",
+                    ],
+                )?;
+                if let Some(host) = orbit.host {
+                    check("stars.orbit.host", host < i, "must name an earlier star")?;
+                    extent += reach[host];
+                }
+                extent += orbit.a * (1. + orbit.e);
+            }
+            reach.push(extent);
+        }
+
+        check_orbit(
+            orbit,
+            [
+                "orbit.period_time",
+                "orbit.per_time",
+                "orbit.e",
+                "orbit.a",
+                "orbit.inclination",
+                "orbit.ascending_node",
+                "orbit.arg_periapsis",
+                "orbit.tolerance",
+            ],
+        )?;
+        let clearance = match orbit.host {
+            Some(host) => {
+                check(
+                    "orbit.host",
+                    host < stars.len(),
+                    "must name one of the stars",
+                )?;
+                stars[host].radius
+            }
+            None => reach.iter().copied().fold(0., Float::max),
+        };
+        check(
+            "orbit",
+            orbit.a * (1. - orbit.e) > clearance,
+            "periapsis must clear the stars",
+        )?;
+
+        positive("planet.radius", planet.radius)?;
+        finite("planet.obliquity", planet.obliquity)?;
+        finite("planet.axis_azimuth", planet.axis_azimuth)?;
+        positive("planet.rotation_period", planet.rotation_period)?;
+
+        for moon in moons {
+            check_orbit(
+                &moon.orbit,
+                [
+                    "moons.orbit.period_time",
+                    "moons.orbit.per_time",
+                    "moons.orbit.e",
+                    "moons.orbit.a",
+                    "moons.orbit.inclination",
+                    "moons.orbit.ascending_node",
+                    "moons.orbit.arg_periapsis",
+                    "moons.orbit.tolerance",
+                ],
+            )?;
+            check(
+                "moons.orbit.host",
+                moon.orbit.host.is_none(),
+                "moons always orbit the planet",
+            )?;
+            positive("moons.body.radius", moon.body.radius)?;
+            finite("moons.body.obliquity", moon.body.obliquity)?;
+            finite("moons.body.axis_azimuth", moon.body.axis_azimuth)?;
+            positive("moons.body.rotation_period", moon.body.rotation_period)?;
+            check(
+                "moons.orbit",
+                moon.orbit.a * (1. - moon.orbit.e) > planet.radius + moon.body.radius,
+                "periapsis must clear the planet",
+            )?;
+        }
+
+        positive("diffusion.kappa", diffusion.kappa)?;
+        positive("diffusion.cfl", diffusion.cfl)?;
+        if let Some(adaptive) = &diffusion.adaptive {
+            check(
+                "diffusion.adaptive",
+                diffusion.scheme.is_embedded(),
+                "needs a scheme with an error estimate, such as rk45",
+            )?;
+            positive("diffusion.adaptive.tolerance", adaptive.tolerance)?;
+            finite("diffusion.adaptive.safety", adaptive.safety)?;
+            check(
+                "diffusion.adaptive.safety",
+                adaptive.safety > 0. && adaptive.safety <= 1.,
+                "must be in (0, 1]",
+            )?;
+            finite("diffusion.adaptive.max_growth", adaptive.max_growth)?;
+            check(
+                "diffusion.adaptive.max_growth",
+                adaptive.max_growth > 1.,
+                "must be greater than 1",
+            )?;
+            finite("diffusion.adaptive.min_shrink", adaptive.min_shrink)?;
+            check(
+                "diffusion.adaptive.min_shrink",
+                adaptive.min_shrink > 0. && adaptive.min_shrink < 1.,
+                "must be in (0, 1)",
+            )?;
+        }
+
+        unit_interval("flux.rho", flux.rho)?;
+        unit_interval("flux.r", flux.r)?;
+        unit_interval("flux.omega", flux.omega)?;
+        non_negative("flux.tau", flux.tau)?;
+        positive("flux.m_eff", flux.m_eff)?;
+        check(
+            "flux.transmission_table",
+            flux.transmission_table != 1,
+            "needs at least 2 samples, or 0 to disable it",
+        )?;
+
+        unit_interval("radiation.eps", radiation.eps)?;
+        non_negative("radiation.sigma", radiation.sigma)?;
+        positive("radiation.c", radiation.c)?;
+
+        if let Some(atmosphere) = atmosphere {
+            unit_interval("atmosphere.eps", atmosphere.eps)?;
+            positive("atmosphere.c", atmosphere.c)?;
+            check(
+                "atmosphere",
+                coupling.splitting != Splitting::Coupled,
+                "needs split stepping, coupled stepping only integrates the surface",
+            )?;
+        }
+
+        non_negative("forcing.acceleration", forcing.acceleration)?;
+        if let Some(period) = forcing.precession_period {
+            finite("forcing.precession_period", period)?;
+            check("forcing.precession_period", period != 0., "must not be 0")?;
+        }
+        for cycle in &forcing.cycles {
+            finite("forcing.cycles.amplitude", cycle.amplitude)?;
+            finite("forcing.cycles.phase", cycle.phase)?;
+            positive("forcing.cycles.period", cycle.period)?;
+        }
+        for sample in &forcing.series {
+            finite("forcing.series.time", sample.time)?;
+            finite("forcing.series.obliquity", sample.obliquity)?;
+            finite("forcing.series.axis_azimuth", sample.axis_azimuth)?;
+        }
+        check(
+            "forcing.series",
+            forcing.series.windows(2).all(|w| w[0].time < w[1].time),
+            "times must be strictly increasing",
+        )?;
+        let (low, high) = forcing.series.iter().fold(
+            if forcing.series.is_empty() {
+                (orbit.e, orbit.e)
+            } else {
+                (Float::INFINITY, -Float::INFINITY)
+            },
+            |(low, high), sample| (low.min(sample.eccentricity), high.max(sample.eccentricity)),
+        );
+        let swing: Float = forcing
+            .cycles
+            .iter()
+            .filter(|cycle| cycle.element == Element::Eccentricity)
+            .map(|cycle| cycle.amplitude.abs())
+            .sum();
+        check(
+            "forcing",
+            low - swing >= 0. && high + swing < 1.,
+            "eccentricity must stay in [0, 1)",
+        )?;
+
+        for (i, entry) in planets.iter().enumerate() {
+            check("planets.name", !entry.name.is_empty(), "must not be empty")?;
+            check(
+                "planets.name",
+                planets[..i].iter().all(|other| other.name != entry.name),
+                "must be unique",
+            )?;
+        }
+        if let Some(system) = system {
+            check(
+                "system.name",
+                system.planets.contains(&system.name),
+                "must be one of `system.planets`",
+            )?;
+            positive("system.year", system.year)?;
+            check(
+                "planets",
+                planets.is_empty(),
+                "one planet of a system cannot list planets of its own",
+            )?;
+        }
+        if !planets.is_empty() {
+            for (name, config) in self.planet_configs() {
+                config.validate().map_err(|err| ConfigError::Planet {
+                    name,
+                    source: Box::new(err),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The checks every orbit needs, reported under `names` for its
+/// `period_time`, `per_time`, `e`, `a`, `inclination`, `ascending_node`,
+/// `arg_periapsis` and `tolerance` in that order.
+fn check_orbit(orbit: &OrbitConfig, names: [&'static str; 8]) -> Result<(), ConfigError> {
+    let [
+        period_time,
+        per_time,
+        e,
+        a,
+        inclination,
+        ascending_node,
+        arg_periapsis,
+        tolerance,
+    ] = names;
+    positive(period_time, orbit.period_time)?;
+    finite(per_time, orbit.per_time)?;
+    check(
+        e,
+        (0. ..1.).contains(&orbit.e),
+        "must be in [0, 1) for a closed orbit",
+    )?;
+    positive(a, orbit.a)?;
+    finite(inclination, orbit.inclination)?;
+    finite(ascending_node, orbit.ascending_node)?;
+    finite(arg_periapsis, orbit.arg_periapsis)?;
+    positive(tolerance, orbit.tolerance)
+}
+
+fn check(field: &'static str, ok: bool, reason: &str) -> Result<(), ConfigError> {
+    if ok {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid {
+            field,
+            reason: reason.to_string(),
+        })
+    }
+}
+
+fn finite(field: &'static str, value: Float) -> Result<(), ConfigError> {
+    check(field, value.is_finite(), "must be a finite number")
+}
+
+fn positive(field: &'static str, value: Float) -> Result<(), ConfigError> {
+    finite(field, value)?;
+    check(field, value > 0., "must be greater than 0")
+}
+
+fn non_negative(field: &'static str, value: Float) -> Result<(), ConfigError> {
+    finite(field, value)?;
+    check(field, value >= 0., "must not be negative")
+}
+
+fn unit_interval(field: &'static str, value: Float) -> Result<(), ConfigError> {
+    finite(field, value)?;
+    check(field, (0. ..=1.).contains(&value), "must be in [0, 1]")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The field a config is rejected for.
+    fn rejected(text: &str) -> &'static str {
+        match SimConfig::from_toml(text) {
+            Err(ConfigError::Invalid { field, .. }) => field,
+            other => panic!("expected an invalid config, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn shipped_configs_load() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../configs");
+        let mut loaded = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "toml")
+            {
+                if let Err(err) = SimConfig::load(&path) {
+                    panic!("{}: {err}", path.display());
+                }
+                loaded += 1;
+            }
+        }
+        assert!(loaded > 0);
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        assert_eq!(rejected("[diffusion]\nkappa = -1e-3"), "diffusion.kappa");
+        assert_eq!(rejected("[orbit]\ne = 1.0"), "orbit.e");
+        assert_eq!(rejected("[orbit]\nhost = 1"), "orbit.host");
+        assert_eq!(
+            rejected("[[planets]]\nname = \"b\"\n[[planets]]\nname = \"b\""),
+            "planets.name"
+        );
+    }
+
+    #[test]
+    fn luminosity_brightens_until_the_end_of_the_main_sequence() {
+        let star = StarConfig {
+            evolution: Some(StarEvolution {
+                age: 0.,
+                reference_age: 100.,
+            }),
+            ..StarConfig::default()
+        };
+        let end = StarEvolution::MAX_AGE * 100.;
+        let mut previous = 0.;
+        for i in 0..=100 {
+            let luminosity = star.luminosity(end * i as Float / 100.);
+            assert!(luminosity.is_finite() && luminosity > previous);
+            previous = luminosity;
+        }
+        assert_eq!(star.luminosity(10. * end), previous);
+    }
+}
@@ -0,0 +1,133 @@
+//! How the physical processes are combined into one model step.
+//!
+//! With splitting each process advances the field on its own, one after
+//! another in the configured order. Lie splitting runs them once each for
+//! the full step, which adds an error of first order in the step. Strang
+//! splitting runs all but the last for half a step, the last for a full step
+//! and then the others again in reverse, which makes the splitting error
+//! second order. Coupled stepping sums all processes into one right-hand
+//! side, leaving only the integrator's error.
+
+use ndarray::{Array2, Zip};
+use serde::{Deserialize, Serialize};
+
+use crate::{diffusion::Diffusion, energy_diff::BlackBody, float::Float, integrator::Rhs};
+
+/// A physical process that changes the temperature field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Process {
+    Diffusion,
+    Insolation,
+    Radiation,
+}
+
+impl Process {
+    pub const ALL: [Process; 3] = [Process::Diffusion, Process::Insolation, Process::Radiation];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Splitting {
+    Lie,
+    Strang,
+    Coupled,
+}
+
+/// All processes as a single right-hand side, per simulated second.
+pub struct Coupled<'a> {
+    diffusion: &'a mut Diffusion,
+    heating: &'a Array2<Float>,
+    black_body: BlackBody,
+    /// Physics ticks per simulated second, since the heat sources are
+    /// calibrated per tick
+    ticks_per_second: Float,
+}
+
+impl<'a> Coupled<'a> {
+    pub fn new(
+        diffusion: &'a mut Diffusion,
+        heating: &'a Array2<Float>,
+        black_body: BlackBody,
+        ticks_per_second: Float,
+    ) -> Self {
+        Coupled {
+            diffusion,
+            heating,
+            black_body,
+            ticks_per_second,
+        }
+    }
+}
+
+impl Rhs for Coupled<'_> {
+    fn eval(&mut self, temp: &Array2<Float>) -> Array2<Float> {
+        let mut rate = Array2::zeros(temp.dim());
+        self.eval_into(temp, &mut rate);
+        rate
+    }
+
+    fn eval_into(&mut self, temp: &Array2<Float>, rate: &mut Array2<Float>) {
+        self.diffusion.eval_into(temp, rate);
+        let scale = self.ticks_per_second;
+        let cooling = self.black_body.rate();
+        Zip::from(rate)
+            .and(temp)
+            .and(self.heating)
+            .par_for_each(|rate, &t, &heat| *rate += scale * (heat - cooling * t.powi(4)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Model, SimConfig, float::widen, grid::Grid, integrator::Method};
+
+    /// Temperature after `span` simulated seconds in `steps` steps, with no
+    /// insolation so that the splitting is the only thing changing with the
+    /// step: diffusion's own steps are fixed by the CFL limit and radiation
+    /// runs RK4, both far more accurate than the splitting.
+    fn run(splitting: Splitting, steps: usize) -> Array2<f64> {
+        let mut config = SimConfig {
+            grid: Grid {
+                width: 16,
+                height: 8,
+            },
+            ..SimConfig::default()
+        };
+        config.initial.seed = Some(5);
+        config.initial.perturbation = 100.;
+        config.stars[0].luminosity = 0.;
+        config.diffusion.kappa = 1e-3;
+        config.diffusion.cfl = 0.1;
+        config.radiation.sigma = 1e-10;
+        config.radiation.scheme = Method::Rk4;
+        config.coupling.splitting = splitting;
+        let mut model = Model::new(config);
+        let span = 100.;
+        for _ in 0..steps {
+            model.step(span / steps as Float);
+        }
+        model.temperature().field().mapv(widen)
+    }
+
+    fn max_error(a: &Array2<f64>, b: &Array2<f64>) -> f64 {
+        Zip::from(a)
+            .and(b)
+            .fold(0., |max, &a, &b| (a - b).abs().max(max))
+    }
+
+    #[test]
+    fn lie_is_first_order_and_strang_second() {
+        let reference = run(Splitting::Strang, 256);
+        for (splitting, expected) in [(Splitting::Lie, 1.), (Splitting::Strang, 2.)] {
+            let coarse = max_error(&run(splitting, 8), &reference);
+            let fine = max_error(&run(splitting, 16), &reference);
+            let order = (coarse / fine).log2();
+            assert!(
+                (order - expected).abs() < 0.25,
+                "{splitting:?} converges with order {order}"
+            );
+        }
+    }
+}
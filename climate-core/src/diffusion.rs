@@ -0,0 +1,555 @@
+//! Conservative finite-volume discretisation of the Laplace–Beltrami operator
+//! on the unit sphere.
+//!
+//! Every cell exchanges heat with its neighbours through its faces, with a
+//! conductance of face length over centre distance. Each face flux is added
+//! to one cell and taken from the other, so the area-weighted total heat is
+//! conserved to round-off.
+//!
+//! The two polar rows are treated as caps: each is a single control volume
+//! around its pole whose temperature is the mean of the row. A cap exchanges
+//! heat with every cell of the ring next to it and hands the same tendency
+//! to all its sub-cells.
+//!
+//! [`Diffusion`] scales the operator by the diffusivity for the integrators
+//! and hands the θ-method to [`ImplicitSolver`], whose step is not tied to
+//! the resolution.
+
+#![allow(non_snake_case)]
+
+use std::{f64::consts::PI, fmt, sync::Arc};
+
+use ndarray::{Array2, Axis, parallel::prelude::*};
+use rayon::prelude::*;
+use rustfft::{Fft, FftPlanner, num_complex::Complex};
+
+use crate::{
+    float::{Float, widen},
+    grid::Grid,
+    integrator::Rhs,
+};
+
+/// Geometric coefficients of the stencil, one entry per row or face row.
+#[derive(Clone, Debug)]
+pub struct Stencil {
+    /// 1 / cell area, or 1 / cap area on the polar rows
+    inv_area: Vec<Float>,
+    /// Conductance of the faces between neighbouring cells in a row
+    zonal: Vec<Float>,
+    /// Conductance of the faces between row `y` and row `y + 1`
+    meridional: Vec<Float>,
+}
+
+impl Stencil {
+    pub fn new(grid: &Grid) -> Self {
+        let (dlon, dcolat) = (grid.dlon(), grid.dcolat());
+        let last = grid.height - 1;
+
+        // Area-weighted mean distance of a cap from its pole
+        let cap_centroid = (dcolat.sin() - dcolat * dcolat.cos()) / (1. - dcolat.cos());
+
+        let inv_area = (0..grid.height)
+            .map(|y| {
+                if y == 0 || y == last {
+                    1. / (grid.width as Float * grid.area(y))
+                } else {
+                    1. / grid.area(y)
+                }
+            })
+            .collect();
+
+        // No zonal exchange inside a cap
+        let zonal = (0..grid.height)
+            .map(|y| {
+                if y == 0 || y == last {
+                    0.
+                } else {
+                    dcolat / (grid.colat(y).sin() * dlon)
+                }
+            })
+            .collect();
+
+        let meridional = (0..last)
+            .map(|y| {
+                let length = grid.colat_edge(y + 1).sin() * dlon;
+                let distance = if y == 0 || y + 1 == last {
+                    grid.colat(1) - cap_centroid
+                } else {
+                    dcolat
+                };
+                length / distance
+            })
+            .collect();
+
+        Stencil {
+            inv_area,
+            zonal,
+            meridional,
+        }
+    }
+
+    /// Apply the operator to `T`, returning dT/dt for unit diffusivity.
+    pub fn apply(&self, T: &Array2<Float>) -> Array2<Float> {
+        let mut out = Array2::zeros(T.dim());
+        self.apply_into(T, &mut out, 1.);
+        out
+    }
+
+    /// `out = scale * L T`, sweeping the meridians in parallel.
+    ///
+    /// Each cell gathers the fluxes through its own faces; a face flux is
+    /// the same number with opposite sign on either side, so the total heat
+    /// is still conserved to round-off.
+    pub fn apply_into(&self, T: &Array2<Float>, out: &mut Array2<Float>, scale: Float) {
+        let (width, height) = T.dim();
+        let last = height - 1;
+        let (inv_area, zonal, meridional) = (&self.inv_area, &self.zonal, &self.meridional);
+
+        // The caps exchange heat with every cell of the ring next to them
+        let cap_temp = [
+            T.column(0).sum() / width as Float,
+            T.column(last).sum() / width as Float,
+        ];
+        let cap_rate = [
+            scale
+                * inv_area[0]
+                * meridional[0]
+                * (T.column(1).sum() - width as Float * cap_temp[0]),
+            scale
+                * inv_area[last]
+                * meridional[last - 1]
+                * (T.column(last - 1).sum() - width as Float * cap_temp[1]),
+        ];
+
+        out.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(x, mut out)| {
+                let west = T.row((x + width - 1) % width);
+                let centre = T.row(x);
+                let east = T.row((x + 1) % width);
+
+                out[0] = cap_rate[0];
+                out[last] = cap_rate[1];
+                for y in 1..last {
+                    let t = centre[y];
+                    let north = if y == 1 { cap_temp[0] } else { centre[y - 1] };
+                    let south = if y + 1 == last {
+                        cap_temp[1]
+                    } else {
+                        centre[y + 1]
+                    };
+                    let inflow = zonal[y] * (east[y] + west[y] - 2. * t)
+                        + meridional[y - 1] * (north - t)
+                        + meridional[y] * (south - t);
+                    out[y] = scale * inv_area[y] * inflow;
+                }
+            });
+    }
+}
+
+/// The diffusion term `κ∇²T` of the heat equation.
+#[derive(Clone, Debug)]
+pub struct Diffusion {
+    grid: Grid,
+    stencil: Stencil,
+    kappa: Float,
+    /// Built on the first implicit step
+    solver: Option<ImplicitSolver>,
+}
+
+impl Diffusion {
+    pub fn new(grid: &Grid, kappa: Float) -> Self {
+        Diffusion {
+            grid: *grid,
+            stencil: Stencil::new(grid),
+            kappa,
+            solver: None,
+        }
+    }
+}
+
+impl Rhs for Diffusion {
+    fn eval(&mut self, T: &Array2<Float>) -> Array2<Float> {
+        let mut dT = Array2::zeros(T.dim());
+        self.eval_into(T, &mut dT);
+        dT
+    }
+
+    fn eval_into(&mut self, T: &Array2<Float>, dT: &mut Array2<Float>) {
+        self.stencil.apply_into(T, dT, self.kappa);
+    }
+
+    fn solve(&mut self, T: &mut Array2<Float>, h: Float, theta: Float) {
+        let grid = self.grid;
+        let solver = self
+            .solver
+            .get_or_insert_with(|| ImplicitSolver::new(&grid));
+        solver.step(T, widen(self.kappa) * widen(h), widen(theta));
+    }
+}
+
+/// The Laplace–Beltrami operator of `T` on the grid given by its shape.
+pub fn laplacian(T: &Array2<Float>) -> Array2<Float> {
+    Stencil::new(&Grid::from_dim(T.dim())).apply(T)
+}
+
+/// Area-weighted total heat of a field, the quantity the operator conserves.
+pub fn total_heat(T: &Array2<Float>) -> f64 {
+    let grid = Grid::from_dim(T.dim());
+    T.indexed_iter()
+        .map(|((_, y), t)| widen(*t) * widen(grid.area(y)))
+        .sum()
+}
+
+/// θ-scheme `(I - θhL) T' = (I + (1 - θ)hL) T` for the [`Stencil`] operator.
+///
+/// The solve runs in double precision: far beyond the explicit limit the
+/// identity is tiny next to `θhL`, and single precision would lose the
+/// total heat.
+///
+/// The operator doesn't change under zonal shifts, so a DFT along each row
+/// splits it into one tridiagonal system in colatitude per zonal wavenumber.
+/// A cap only holds its row mean and so only takes part in the
+/// wavenumber-0 system. Each step costs two FFTs and `width` Thomas solves,
+/// with the rows transformed and the wavenumbers solved in parallel.
+#[derive(Clone)]
+pub struct ImplicitSolver {
+    width: usize,
+    height: usize,
+    /// Rows of L for each wavenumber, indexed `[k * height + y]`
+    lower: Vec<f64>,
+    diag: Vec<f64>,
+    upper: Vec<f64>,
+    /// Diffusive step and θ the factorisation below is for
+    h: f64,
+    theta: f64,
+    /// Thomas factors of `I - θhL`
+    c_prime: Vec<f64>,
+    inv_pivot: Vec<f64>,
+    fft: Arc<dyn Fft<f64>>,
+    ifft: Arc<dyn Fft<f64>>,
+    scratch_len: usize,
+    /// Rows of the field and their spectra, row `y` at `y * width`
+    rows: Vec<Complex<f64>>,
+    /// The spectra by wavenumber, wavenumber `k` at `k * height`
+    spectra: Vec<Complex<f64>>,
+}
+
+impl ImplicitSolver {
+    pub fn new(grid: &Grid) -> Self {
+        let Stencil {
+            inv_area,
+            zonal,
+            meridional,
+        } = Stencil::new(grid);
+        let wide = |v: Vec<Float>| v.into_iter().map(widen).collect::<Vec<_>>();
+        let (inv_area, zonal, meridional) = (wide(inv_area), wide(zonal), wide(meridional));
+        let Grid { width, height } = *grid;
+        let last = height - 1;
+
+        let len = width * height;
+        let (mut lower, mut diag, mut upper) = (vec![0.; len], vec![0.; len], vec![0.; len]);
+        for k in 0..width {
+            let row = k * height;
+            let wave = 2. * (2. * PI * k as f64 / width as f64).cos() - 2.;
+            for y in 1..last {
+                let (north, south) = (meridional[y - 1], meridional[y]);
+                diag[row + y] = inv_area[y] * (zonal[y] * wave - north - south);
+                // The caps have no zonal structure to couple to
+                if y > 1 || k == 0 {
+                    lower[row + y] = inv_area[y] * north;
+                }
+                if y + 1 < last || k == 0 {
+                    upper[row + y] = inv_area[y] * south;
+                }
+            }
+        }
+        // Summing a row's wavenumber-0 amplitude over its cells gives `width`
+        let north_cap = inv_area[0] * width as f64 * meridional[0];
+        let south_cap = inv_area[last] * width as f64 * meridional[last - 1];
+        diag[0] = -north_cap;
+        upper[0] = north_cap;
+        diag[last] = -south_cap;
+        lower[last] = south_cap;
+
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(width);
+        let ifft = planner.plan_fft_inverse(width);
+        let scratch_len = fft
+            .get_inplace_scratch_len()
+            .max(ifft.get_inplace_scratch_len());
+
+        ImplicitSolver {
+            width,
+            height,
+            lower,
+            diag,
+            upper,
+            h: f64::NAN,
+            theta: f64::NAN,
+            c_prime: vec![0.; len],
+            inv_pivot: vec![0.; len],
+            fft,
+            ifft,
+            scratch_len,
+            rows: vec![Complex::default(); len],
+            spectra: vec![Complex::default(); len],
+        }
+    }
+
+    fn factorise(&mut self, h: f64, theta: f64) {
+        let th = theta * h;
+        for k in 0..self.width {
+            let row = k * self.height;
+            let mut c_prev = 0.;
+            for y in 0..self.height {
+                let i = row + y;
+                let inv_pivot = 1. / (1. - th * self.diag[i] + th * self.lower[i] * c_prev);
+                c_prev = -th * self.upper[i] * inv_pivot;
+                self.c_prime[i] = c_prev;
+                self.inv_pivot[i] = inv_pivot;
+            }
+        }
+        self.h = h;
+        self.theta = theta;
+    }
+
+    /// Advance `T` by the diffusive time `h`, i.e. diffusivity times step.
+    /// `theta` is 1 for backward Euler and 0.5 for Crank–Nicolson. The polar
+    /// rows must already be pooled into caps.
+    pub fn step(&mut self, T: &mut Array2<Float>, h: f64, theta: f64) {
+        if h != self.h || theta != self.theta {
+            self.factorise(h, theta);
+        }
+        let ImplicitSolver {
+            width,
+            height,
+            ref lower,
+            ref diag,
+            ref upper,
+            ref c_prime,
+            ref inv_pivot,
+            ref fft,
+            ref ifft,
+            scratch_len,
+            ref mut rows,
+            ref mut spectra,
+            ..
+        } = *self;
+        let explicit = (1. - theta) * h;
+        let implicit = theta * h;
+        let transform = |rows: &mut [Complex<f64>], fft: &Arc<dyn Fft<f64>>| {
+            rows.par_chunks_mut(width).for_each_init(
+                || vec![Complex::default(); scratch_len],
+                |scratch, row| fft.process_with_scratch(row, scratch),
+            );
+        };
+
+        rows.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (x, r) in row.iter_mut().enumerate() {
+                *r = Complex::new(widen(T[[x, y]]), 0.);
+            }
+        });
+        transform(rows, fft);
+
+        spectra
+            .par_chunks_mut(height)
+            .enumerate()
+            .for_each(|(k, column)| {
+                let row = k * height;
+                for (y, c) in column.iter_mut().enumerate() {
+                    *c = rows[y * width + k];
+                }
+
+                // Thomas forward sweep over the right-hand side, built on the
+                // fly from the not yet overwritten spectrum
+                let mut above = Complex::default();
+                let mut prev = Complex::default();
+                for y in 0..height {
+                    let i = row + y;
+                    let t = column[y];
+                    let mut lt = diag[i] * t;
+                    if y > 0 {
+                        lt += lower[i] * above;
+                    }
+                    if y + 1 < height {
+                        lt += upper[i] * column[y + 1];
+                    }
+                    let d = t + explicit * lt;
+                    prev = (d + implicit * lower[i] * prev) * inv_pivot[i];
+                    column[y] = prev;
+                    above = t;
+                }
+                let mut next = Complex::default();
+                for y in (0..height).rev() {
+                    next = column[y] - c_prime[row + y] * next;
+                    column[y] = next;
+                }
+            });
+
+        rows.par_chunks_mut(width).enumerate().for_each(|(y, row)| {
+            for (k, r) in row.iter_mut().enumerate() {
+                *r = spectra[k * height + y];
+            }
+        });
+        transform(rows, ifft);
+
+        let norm = 1. / width as f64;
+        T.axis_iter_mut(Axis(0))
+            .into_par_iter()
+            .enumerate()
+            .for_each(|(x, mut column)| {
+                for (y, t) in column.iter_mut().enumerate() {
+                    *t = (rows[y * width + x].re * norm) as Float;
+                }
+            });
+    }
+}
+
+impl fmt::Debug for ImplicitSolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ImplicitSolver")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("theta", &self.theta)
+            .field("h", &self.h)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::DiffusionConfig,
+        integrator::{Integrator, Method, Workspace},
+        temp::TempMap,
+    };
+
+    const GRID: Grid = Grid {
+        width: 48,
+        height: 16,
+    };
+
+    /// A lumpy field with the caps already pooled, as the model hands it to
+    /// diffusion.
+    fn lumpy() -> Array2<Float> {
+        let mut temp = TempMap::zeros(&GRID);
+        temp.perturb(11, 100.);
+        temp.pool_polar_caps();
+        temp.field().clone()
+    }
+
+    /// Relative change of the total heat after `steps` steps of `h`.
+    fn drift(method: Method, h: Float, steps: usize) -> f64 {
+        let mut diffusion = Diffusion::new(&GRID, DiffusionConfig::default().kappa);
+        let mut work = Workspace::default();
+        let mut temp = lumpy();
+        let before = total_heat(&temp);
+        for _ in 0..steps {
+            method.advance(&mut diffusion, &mut temp, h, &mut work);
+        }
+        (total_heat(&temp) - before).abs() / before
+    }
+
+    /// Round-off in the field's float type, well short of any real leak
+    fn tolerance() -> f64 {
+        100. * widen(Float::EPSILON)
+    }
+
+    #[test]
+    fn explicit_steps_conserve_heat() {
+        let h = DiffusionConfig::default().dt(&GRID);
+        for method in [Method::Euler, Method::Rk4, Method::Rk45] {
+            let drift = drift(method, h, 200);
+            assert!(drift < tolerance(), "{method:?} drifted by {drift:e}");
+        }
+    }
+
+    #[test]
+    fn implicit_steps_conserve_heat() {
+        // Far beyond the explicit limit, where the implicit solve earns its keep
+        let h = 1e3 * DiffusionConfig::default().dt(&GRID);
+        for method in [Method::BackwardEuler, Method::CrankNicolson] {
+            let drift = drift(method, h, 50);
+            assert!(drift < tolerance(), "{method:?} drifted by {drift:e}");
+        }
+    }
+
+    #[test]
+    fn diffusion_smooths_the_field() {
+        let temp = lumpy();
+        let mut smoothed = temp.clone();
+        let mut diffusion = Diffusion::new(&GRID, DiffusionConfig::default().kappa);
+        let h = 1e3 * DiffusionConfig::default().dt(&GRID);
+        Method::BackwardEuler.advance(&mut diffusion, &mut smoothed, h, &mut Workspace::default());
+        let spread = |t: &Array2<Float>| {
+            t.fold(Float::NEG_INFINITY, |a, &b| a.max(b))
+                - t.fold(Float::INFINITY, |a, &b| a.min(b))
+        };
+        assert!(spread(&smoothed) < spread(&temp));
+    }
+
+    #[test]
+    fn laplacian_of_cos_colatitude_converges_at_second_order() {
+        // ∇² cos θ = -2 cos θ, compared on cell averages, which the finite
+        // volumes stand for. The single-cell caps and the rings next to them
+        // are far coarser, so the comparison keeps to mid-latitudes.
+        let error = |height: usize| {
+            let grid = Grid {
+                width: 2 * height,
+                height,
+            };
+            let dcolat = PI / height as f64;
+            // Worked out in double precision, where the cancellation is
+            // harmless
+            let average = |y: usize| {
+                let (north, south) = (y as f64 * dcolat, (y + 1) as f64 * dcolat);
+                (south.sin().powi(2) - north.sin().powi(2)) / (2. * (north.cos() - south.cos()))
+            };
+            let temp = Array2::from_shape_fn(grid.dim(), |(_, y)| average(y) as Float);
+            let laplacian = Stencil::new(&grid).apply(&temp);
+            (0..height)
+                .filter(|&y| (PI / 4. ..3. * PI / 4.).contains(&widen(grid.colat(y))))
+                .map(|y| (widen(laplacian[[0, y]]) + 2. * average(y)).abs())
+                .fold(0., f64::max)
+        };
+        let order = (error(32) / error(64)).log2();
+        assert!((order - 2.).abs() < 0.25, "converges with order {order}");
+    }
+
+    #[test]
+    fn crank_nicolson_agrees_with_rk4_for_small_steps() {
+        let span = 1000. * DiffusionConfig::default().dt(&GRID);
+        let start = lumpy();
+        // Largest difference between the two after `span` in `steps` steps
+        let difference = |steps: usize| {
+            let h = span / steps as Float;
+            let mut diffusion = Diffusion::new(&GRID, DiffusionConfig::default().kappa);
+            let mut work = Workspace::default();
+            let (mut explicit, mut implicit) = (start.clone(), start.clone());
+            for _ in 0..steps {
+                Method::Rk4.advance(&mut diffusion, &mut explicit, h, &mut work);
+                Method::CrankNicolson.advance(&mut diffusion, &mut implicit, h, &mut work);
+            }
+            let largest = |a: &Array2<Float>, b: &Array2<Float>| {
+                a.iter()
+                    .zip(b)
+                    .map(|(a, b)| widen((a - b).abs()))
+                    .fold(0., f64::max)
+            };
+            (largest(&explicit, &implicit), largest(&explicit, &start))
+        };
+        // Still well inside RK4's stability limit, where its error is small
+        // next to Crank–Nicolson's second order one
+        let (coarse, change) = difference(25);
+        let (fine, _) = difference(50);
+        assert!(
+            fine < 1e-4 * change,
+            "{fine} apart after a change of {change}"
+        );
+        let order = (coarse / fine).log2();
+        assert!((order - 2.).abs() < 0.25, "apart at order {order}");
+    }
+}
@@ -0,0 +1,508 @@
+use crate::{
+    config::{AtmosphereConfig, FluxConfig, RadiationConfig},
+    float::{Float, Quat, Vec3, consts::PI},
+    grid::Grid,
+    integrator::Rhs,
+};
+
+use ndarray::{Array2, Axis, Zip};
+
+/// A star as seen from the planet.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Source {
+    pub position: Vec3,
+    /// Radius of the star, which makes eclipses partial at their edges
+    pub radius: Float,
+    /// Irradiance it delivers at the planet's distance
+    pub irradiance: Float,
+}
+
+/// A body that can stand between a star and the one being lit, casting an
+/// eclipse.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Occluder {
+    pub position: Vec3,
+    pub radius: Float,
+}
+
+// There's no way this should be > 1...
+pub fn transmission_f(mu: Float, flux: &FluxConfig) -> Float {
+    // Assuming mu is negative (clamped to 0)
+    if mu > 0. {
+        flux.c_diff() + (-flux.tau / mu).exp()
+    } else {
+        0.
+    }
+}
+
+/// The parts of the insolation that don't change from tick to tick.
+///
+/// Cell normals and the optical constants are worked out once, so every tick
+/// only costs a dot product with the local sun vector and the transmission.
+#[derive(Clone, Debug)]
+pub struct Insolation {
+    /// Outward unit normal of every cell
+    normals: Array2<Vec3>,
+    /// Flux at normal incidence without any absorption per unit of
+    /// irradiance, `1 / (1 - rho r)`
+    gain: Float,
+    flux: FluxConfig,
+    /// `mu * transmission_f(mu)` sampled evenly over `mu` in `[0, 1]`
+    table: Option<Vec<Float>>,
+}
+
+impl Insolation {
+    pub fn new(flux: &FluxConfig, grid: &Grid) -> Self {
+        let table = (flux.transmission_table > 0).then(|| {
+            let last = (flux.transmission_table - 1) as Float;
+            (0..flux.transmission_table)
+                .map(|i| {
+                    let mu = i as Float / last;
+                    mu * transmission_f(mu, flux)
+                })
+                .collect()
+        });
+        Insolation {
+            normals: Array2::from_shape_fn(grid.dim(), |(x, y)| grid.normal(x, y)),
+            gain: 1. / (1.0 - flux.rho * flux.r),
+            flux: flux.clone(),
+            table,
+        }
+    }
+
+    /// Flux on every cell for a planet at `position` with orientation
+    /// `rotation`, summed over the `sources`.
+    pub fn apply_into(
+        &self,
+        position: Vec3,
+        rotation: Quat,
+        sources: &[Source],
+        out: &mut Array2<Float>,
+    ) {
+        let lights: Vec<_> = sources
+            .iter()
+            .map(|source| {
+                // calculate from planet's frame of reference
+                let star_normal = (source.position - position).normalize();
+
+                // Quaternion transformation into local reference frame
+                let local_star_normal = (rotation.conjugate() * star_normal).normalize();
+                (local_star_normal, source.irradiance)
+            })
+            .collect();
+        self.lights_into(&lights, out);
+    }
+
+    /// Flux on every cell of a body of `radius` at `position` with orientation
+    /// `rotation`, summed over the `sources` with whatever part of each star
+    /// the `occluders` hide from the cell taken away.
+    pub fn apply_eclipsed_into(
+        &self,
+        position: Vec3,
+        radius: Float,
+        rotation: Quat,
+        sources: &[Source],
+        occluders: &[Occluder],
+        out: &mut Array2<Float>,
+    ) {
+        if occluders.is_empty() {
+            self.apply_into(position, rotation, sources, out);
+            return;
+        }
+        let gain = self.gain;
+        let zenits: Vec<_> = sources
+            .iter()
+            .map(|source| (rotation.conjugate() * (source.position - position)).normalize())
+            .collect();
+        Zip::from(out)
+            .and(&self.normals)
+            .par_for_each(|flux, normal| {
+                // The cell in the frame of the system, where the shadows fall
+                let point = position + rotation * (*normal * radius);
+                *flux = sources
+                    .iter()
+                    .zip(&zenits)
+                    .map(|(source, zenit)| {
+                        let mu = normal.dot(*zenit).clamp(0., Float::INFINITY);
+                        if mu == 0. {
+                            return 0.;
+                        }
+                        source.irradiance
+                            * gain
+                            * self.transmitted(mu)
+                            * visible_fraction(point, source, occluders)
+                    })
+                    .sum();
+            });
+    }
+
+    /// Flux on every cell with the star in direction `zenit` of the local
+    /// frame, delivering `irradiance` at normal incidence.
+    pub fn flux_into(&self, zenit: Vec3, irradiance: Float, out: &mut Array2<Float>) {
+        self.lights_into(&[(zenit, irradiance)], out);
+    }
+
+    /// Flux on every cell summed over stars given as local direction and
+    /// irradiance, each at its own zenith angle.
+    fn lights_into(&self, lights: &[(Vec3, Float)], out: &mut Array2<Float>) {
+        let gain = self.gain;
+        Zip::from(out)
+            .and(&self.normals)
+            .par_for_each(|flux, normal| {
+                *flux = lights
+                    .iter()
+                    .map(|&(zenit, irradiance)| {
+                        let mu = normal.dot(zenit).clamp(0., Float::INFINITY);
+                        irradiance * gain * self.transmitted(mu)
+                    })
+                    .sum();
+            });
+    }
+
+    /// `mu * transmission_f(mu)`, from the table if there is one.
+    fn transmitted(&self, mu: Float) -> Float {
+        match &self.table {
+            Some(table) => {
+                let pos = mu.min(1.) * (table.len() - 1) as Float;
+                let i = (pos as usize).min(table.len() - 2);
+                let frac = pos - i as Float;
+                table[i] + frac * (table[i + 1] - table[i])
+            }
+            None => mu * transmission_f(mu, &self.flux),
+        }
+    }
+}
+
+/// Fraction of the disk of `source` seen from `point` past the `occluders`.
+///
+/// Each occluder covers its share of the disk on its own, so where two of
+/// them overlap in front of the same star the eclipse comes out slightly too
+/// shallow.
+fn visible_fraction(point: Vec3, source: &Source, occluders: &[Occluder]) -> Float {
+    let to_star = source.position - point;
+    let distance = to_star.length();
+    let star = (source.radius / distance).min(1.).asin();
+    occluders.iter().fold(1., |visible, occluder| {
+        let to_occluder = occluder.position - point;
+        let reach = to_occluder.length();
+        if reach >= distance {
+            return visible;
+        }
+        let size = (occluder.radius / reach).min(1.).asin();
+        let separation = to_star.angle_between(to_occluder);
+        let hidden = if star > 0. {
+            disk_overlap(star, size, separation) / (PI * star * star)
+        } else if separation < size {
+            1.
+        } else {
+            0.
+        };
+        visible * (1. - hidden).max(0.)
+    })
+}
+
+/// Area two disks of angular radius `a` and `b` share with their centers
+/// `separation` apart, small enough to treat the sky as flat.
+fn disk_overlap(a: Float, b: Float, separation: Float) -> Float {
+    let d = separation;
+    if d >= a + b {
+        0.
+    } else if d <= (a - b).abs() {
+        PI * a.min(b).powi(2)
+    } else {
+        let lens_a = ((d * d + a * a - b * b) / (2. * d * a))
+            .clamp(-1., 1.)
+            .acos();
+        let lens_b = ((d * d + b * b - a * a) / (2. * d * b))
+            .clamp(-1., 1.)
+            .acos();
+        let kite = ((-d + a + b) * (d + a - b) * (d - a + b) * (d + a + b))
+            .max(0.)
+            .sqrt();
+        a * a * lens_a + b * b * lens_b - kite / 2.
+    }
+}
+
+/// Newton iterations per cell for an implicit black-body step
+const NEWTON_ITERATIONS: usize = 8;
+
+/// Black-body cooling `-εσT⁴ dA / c`, per physics tick.
+#[derive(Clone, Copy, Debug)]
+pub struct BlackBody {
+    rate: Float,
+}
+
+impl BlackBody {
+    pub fn new(radiation: &RadiationConfig, grid: &Grid) -> Self {
+        BlackBody {
+            rate: radiation.eps * radiation.sigma * grid.da() / radiation.c,
+        }
+    }
+
+    /// Cooling per tick is `rate * T⁴`.
+    pub fn rate(&self) -> Float {
+        self.rate
+    }
+}
+
+impl Rhs for BlackBody {
+    fn eval(&mut self, temp: &Array2<Float>) -> Array2<Float> {
+        let mut rate = Array2::zeros(temp.dim());
+        self.eval_into(temp, &mut rate);
+        rate
+    }
+
+    fn eval_into(&mut self, temp: &Array2<Float>, rate: &mut Array2<Float>) {
+        let k = self.rate;
+        Zip::from(rate)
+            .and(temp)
+            .par_for_each(|rate, &t| *rate = -k * t.powi(4));
+    }
+
+    // Every cell cools on its own, so the implicit equation is solved by
+    // Newton's method cell by cell.
+    fn solve(&mut self, temp: &mut Array2<Float>, h: Float, theta: Float) {
+        let k = self.rate;
+        let implicit = theta * h * k;
+        temp.par_mapv_inplace(|t| {
+            let known = t - (1. - theta) * h * k * t.powi(4);
+            let mut next = t;
+            for _ in 0..NEWTON_ITERATIONS {
+                let delta =
+                    (next + implicit * next.powi(4) - known) / (1. + 4. * implicit * next.powi(3));
+                next -= delta;
+                if delta.abs() <= Float::EPSILON * next.abs() {
+                    break;
+                }
+            }
+            next
+        });
+    }
+}
+
+/// Longwave exchange between the surface and a gray atmosphere layer, per
+/// physics tick.
+///
+/// It works on the surface and the layer fields stacked along the first
+/// axis, so that both advance together under one integrator. The surface
+/// emits `εσT⁴`, of which the layer absorbs the fraction `ε_a`; the layer
+/// emits `ε_a σ T_a⁴` both up and down, and the surface absorbs its share of
+/// what comes down.
+#[derive(Clone, Copy, Debug)]
+pub struct GrayAtmosphere {
+    /// Surface cooling per tick is `surface * T⁴`
+    surface: Float,
+    /// Surface warming by the layer per tick is `back * T_a⁴`
+    back: Float,
+    /// Warming of the layer by the surface per tick is `absorbed * T⁴`
+    absorbed: Float,
+    /// Cooling of the layer, up and down together, per tick is
+    /// `emitted * T_a⁴`
+    emitted: Float,
+}
+
+impl GrayAtmosphere {
+    pub fn new(radiation: &RadiationConfig, atmosphere: &AtmosphereConfig, grid: &Grid) -> Self {
+        let surface_flux = radiation.eps * radiation.sigma * grid.da();
+        let layer_flux = atmosphere.eps * radiation.sigma * grid.da();
+        GrayAtmosphere {
+            surface: surface_flux / radiation.c,
+            back: radiation.eps * layer_flux / radiation.c,
+            absorbed: atmosphere.eps * surface_flux / atmosphere.c,
+            emitted: 2. * layer_flux / atmosphere.c,
+        }
+    }
+
+    /// Rates of the surface and the layer at temperatures `t` and `t_a`.
+    fn rates(&self, t: Float, t_a: Float) -> (Float, Float) {
+        let (t4, t_a4) = (t.powi(4), t_a.powi(4));
+        (
+            -self.surface * t4 + self.back * t_a4,
+            self.absorbed * t4 - self.emitted * t_a4,
+        )
+    }
+}
+
+impl Rhs for GrayAtmosphere {
+    fn eval(&mut self, temp: &Array2<Float>) -> Array2<Float> {
+        let mut rate = Array2::zeros(temp.dim());
+        self.eval_into(temp, &mut rate);
+        rate
+    }
+
+    fn eval_into(&mut self, temp: &Array2<Float>, rate: &mut Array2<Float>) {
+        let cells = temp.nrows() / 2;
+        let (surface, layer) = temp.view().split_at(Axis(0), cells);
+        let (surface_rate, layer_rate) = rate.view_mut().split_at(Axis(0), cells);
+        let k = *self;
+        Zip::from(surface_rate)
+            .and(layer_rate)
+            .and(surface)
+            .and(layer)
+            .par_for_each(|surface_rate, layer_rate, &t, &t_a| {
+                (*surface_rate, *layer_rate) = k.rates(t, t_a);
+            });
+    }
+
+    // The surface and the layer above it only exchange heat with each other,
+    // so the implicit equation is solved by Newton's method on each pair.
+    fn solve(&mut self, temp: &mut Array2<Float>, h: Float, theta: Float) {
+        let cells = temp.nrows() / 2;
+        let (surface, layer) = temp.view_mut().split_at(Axis(0), cells);
+        let k = *self;
+        let implicit = theta * h;
+        Zip::from(surface).and(layer).par_for_each(|t, t_a| {
+            let (rate, layer_rate) = k.rates(*t, *t_a);
+            let known = (
+                *t + (1. - theta) * h * rate,
+                *t_a + (1. - theta) * h * layer_rate,
+            );
+            let (mut next, mut next_a) = (*t, *t_a);
+            for _ in 0..NEWTON_ITERATIONS {
+                let (rate, layer_rate) = k.rates(next, next_a);
+                let residual = (
+                    next - implicit * rate - known.0,
+                    next_a - implicit * layer_rate - known.1,
+                );
+                // Jacobian of the residual, 1 - θh ∂f
+                let (t3, t_a3) = (4. * next.powi(3), 4. * next_a.powi(3));
+                let j11 = 1. + implicit * k.surface * t3;
+                let j12 = -implicit * k.back * t_a3;
+                let j21 = -implicit * k.absorbed * t3;
+                let j22 = 1. + implicit * k.emitted * t_a3;
+                let det = j11 * j22 - j12 * j21;
+                let delta = (
+                    (j22 * residual.0 - j12 * residual.1) / det,
+                    (j11 * residual.1 - j21 * residual.0) / det,
+                );
+                next -= delta.0;
+                next_a -= delta.1;
+                if delta.0.abs() <= Float::EPSILON * next.abs()
+                    && delta.1.abs() <= Float::EPSILON * next_a.abs()
+                {
+                    break;
+                }
+            }
+            (*t, *t_a) = (next, next_a);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        float::Float,
+        integrator::{Integrator, Method, Workspace},
+    };
+    use ndarray::concatenate;
+
+    const GRID: Grid = Grid {
+        width: 24,
+        height: 12,
+    };
+
+    fn close(a: Float, b: Float, tolerance: Float) -> bool {
+        (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.)
+    }
+
+    /// Area two disks of radius `a` share with their centers `d` apart.
+    fn lens(a: Float, d: Float) -> Float {
+        2. * a * a * (d / (2. * a)).acos() - d / 2. * (4. * a * a - d * d).sqrt()
+    }
+
+    #[test]
+    fn insolation_matches_the_flux_of_each_cell() {
+        let flux = FluxConfig::default();
+        let position = Vec3::new(3., -4., 0.5);
+        let rotation = Quat::from_rotation_x(0.4) * Quat::from_rotation_z(1.1);
+        let source = Source {
+            position: Vec3::new(-1., 2., 0.),
+            radius: 0.05,
+            irradiance: 0.7,
+        };
+        let mut out = Array2::zeros(GRID.dim());
+        Insolation::new(&flux, &GRID).apply_into(position, rotation, &[source], &mut out);
+
+        let zenit = rotation.conjugate() * (source.position - position).normalize();
+        for ((x, y), &value) in out.indexed_iter() {
+            let mu = GRID.normal(x, y).dot(zenit).max(0.);
+            let expected =
+                source.irradiance / (1. - flux.rho * flux.r) * mu * transmission_f(mu, &flux);
+            assert!(
+                close(value, expected, 1e-5),
+                "cell ({x}, {y}): {value} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn eclipse_geometry() {
+        let point = Vec3::ZERO;
+        let source = Source {
+            position: Vec3::new(10., 0., 0.),
+            radius: 1.,
+            irradiance: 1.,
+        };
+        let occluder = |position, radius| Occluder { position, radius };
+        let star = (0.1 as Float).asin();
+
+        // Off to the side, and on the line but beyond the star
+        let aside = occluder(Vec3::new(5., 3., 0.), 0.5);
+        let behind = occluder(Vec3::new(20., 0., 0.), 5.);
+        assert_eq!(visible_fraction(point, &source, &[aside, behind]), 1.);
+
+        let total = occluder(Vec3::new(5., 0., 0.), 0.6);
+        assert!(visible_fraction(point, &source, &[total]) <= Float::EPSILON);
+
+        let annular = occluder(Vec3::new(5., 0., 0.), 0.25);
+        let size = (0.05 as Float).asin();
+        assert!(close(
+            visible_fraction(point, &source, &[annular]),
+            1. - (size / star).powi(2),
+            1e-5
+        ));
+
+        // Equal disks, from nearly on top of each other to barely touching
+        for d in [0.1, 0.5, 1., 1.5, 1.9] {
+            assert!(
+                close(disk_overlap(1., 1., d), lens(1., d), 1e-5),
+                "separation {d}"
+            );
+        }
+        // An occluder as large as the star, half its radius off the line
+        let center = Vec3::new(5., 5. * (star / 2.).tan(), 0.);
+        let partial = occluder(center, center.length() * star.sin());
+        assert!(close(
+            visible_fraction(point, &source, &[partial]),
+            1. - lens(star, star / 2.) / (PI * star * star),
+            1e-3
+        ));
+    }
+
+    #[test]
+    fn gray_atmosphere_settles_into_radiative_equilibrium() {
+        let radiation = RadiationConfig {
+            // Heavy enough to hold the surface at its temperature
+            c: 1e12,
+            ..RadiationConfig::default()
+        };
+        let layer = AtmosphereConfig::default();
+        let mut gray = GrayAtmosphere::new(&radiation, &layer, &GRID);
+        let surface = Array2::from_elem(GRID.dim(), 300.);
+        let mut stacked = concatenate![Axis(0), surface, Array2::<Float>::zeros(GRID.dim())];
+        let mut work = Workspace::default();
+        for _ in 0..50 {
+            Method::BackwardEuler.advance(&mut gray, &mut stacked, 100., &mut work);
+        }
+
+        // The layer emits up and down what it absorbs, T_a⁴ = T⁴ / 2, and the
+        // surface loses εσT⁴ (1 - ε_a / 2) on balance
+        let (t, t_a) = (stacked[[0, 0]], stacked[[GRID.width, 0]]);
+        assert!(close(t_a.powi(4), t.powi(4) / 2., 1e-4), "{t_a} for {t}");
+        let (surface_rate, layer_rate) = gray.rates(t, t_a);
+        let emitted = radiation.eps * radiation.sigma * GRID.da() * t.powi(4) / radiation.c;
+        assert!(layer_rate.abs() <= 1e-4 * gray.absorbed * t.powi(4));
+        assert!(close(-surface_rate, emitted * (1. - layer.eps / 2.), 1e-4));
+    }
+}
@@ -0,0 +1,57 @@
+//! The floating point type of the model state.
+//!
+//! The model runs in single precision by default. The `f64` feature switches
+//! the fields, the clock and the orbit, vectors and rotations included, to
+//! double precision for long integrations and regression baselines. The
+//! `narrow` helpers hand values to code that always works in `f32`, such as
+//! the renderer, and `widen` to code that always works in `f64`.
+
+#[cfg(not(feature = "f64"))]
+mod precision {
+    pub use glam::{Quat, Vec3};
+    pub use std::f32::consts;
+
+    pub type Float = f32;
+
+    pub fn narrow(x: Float) -> f32 {
+        x
+    }
+
+    pub fn widen(x: Float) -> f64 {
+        f64::from(x)
+    }
+
+    pub fn narrow_vec3(v: Vec3) -> glam::Vec3 {
+        v
+    }
+
+    pub fn narrow_quat(q: Quat) -> glam::Quat {
+        q
+    }
+}
+
+#[cfg(feature = "f64")]
+mod precision {
+    pub use glam::{DQuat as Quat, DVec3 as Vec3};
+    pub use std::f64::consts;
+
+    pub type Float = f64;
+
+    pub fn narrow(x: Float) -> f32 {
+        x as f32
+    }
+
+    pub fn widen(x: Float) -> f64 {
+        x
+    }
+
+    pub fn narrow_vec3(v: Vec3) -> glam::Vec3 {
+        v.as_vec3()
+    }
+
+    pub fn narrow_quat(q: Quat) -> glam::Quat {
+        q.as_quat()
+    }
+}
+
+pub use precision::*;
@@ -0,0 +1,185 @@
+//! Long-term orbital forcing.
+//!
+//! Over tens of thousands of years the eccentricity, the obliquity and the
+//! direction the spin axis leans all drift, and the seasons drift with them:
+//! the Milankovitch cycles. The forcing varies those elements over time,
+//! either as synthetic periodic cycles or from a tabulated series. Forcing
+//! time is counted in years, `forcing.acceleration` of them per orbit of the
+//! run, so cycles of 10⁴–10⁵ years fit into a run of a few hundred orbits.
+//! With several planets the orbit is the top-level one, so the whole system
+//! shares a single forcing clock.
+
+use std::{fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::ConfigError,
+    float::{Float, consts::PI},
+};
+
+/// An element the forcing can vary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    Eccentricity,
+    Obliquity,
+    AxisAzimuth,
+}
+
+/// A sinusoidal variation of one element around its configured value.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cycle {
+    pub element: Element,
+    /// Largest deviation, in degrees for the angles
+    pub amplitude: Float,
+    /// Period in forcing years
+    pub period: Float,
+    /// Phase at forcing year 0, in degrees
+    #[serde(default)]
+    pub phase: Float,
+}
+
+impl Cycle {
+    /// Deviation from the configured value after `years` forcing years.
+    pub fn value(&self, years: Float) -> Float {
+        self.amplitude * (2. * PI * years / self.period + self.phase.to_radians()).sin()
+    }
+}
+
+/// The elements at one time of a tabulated series.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sample {
+    /// Forcing years
+    pub time: Float,
+    pub eccentricity: Float,
+    /// Degrees
+    pub obliquity: Float,
+    /// Degrees, unwrapped so that interpolation follows the precession
+    pub axis_azimuth: Float,
+}
+
+/// Linear interpolation in a series sorted by time, held constant beyond its
+/// ends.
+pub fn interpolate(series: &[Sample], years: Float) -> Sample {
+    let i = series.partition_point(|sample| sample.time <= years);
+    if i == 0 {
+        return series[0];
+    }
+    if i == series.len() {
+        return series[i - 1];
+    }
+    let (a, b) = (series[i - 1], series[i]);
+    let w = (years - a.time) / (b.time - a.time);
+    let lerp = |x: Float, y: Float| x + w * (y - x);
+    Sample {
+        time: years,
+        eccentricity: lerp(a.eccentricity, b.eccentricity),
+        obliquity: lerp(a.obliquity, b.obliquity),
+        axis_azimuth: lerp(a.axis_azimuth, b.axis_azimuth),
+    }
+}
+
+/// Read a series from a CSV file of `time,eccentricity,obliquity,axis_azimuth`
+/// rows. Blank lines, `#` comments and a header row are skipped.
+pub fn read_series(path: &Path) -> Result<Vec<Sample>, ConfigError> {
+    let invalid = |reason: String| ConfigError::Invalid {
+        field: "forcing.table",
+        reason: format!("{}: {reason}", path.display()),
+    };
+    let text = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+
+    let mut series = Vec::new();
+    let mut header_allowed = true;
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let values = line
+            .split(',')
+            .map(|value| value.trim().parse::<Float>())
+            .collect::<Result<Vec<_>, _>>();
+        match values.as_deref() {
+            Ok(&[time, eccentricity, obliquity, axis_azimuth]) => series.push(Sample {
+                time,
+                eccentricity,
+                obliquity,
+                axis_azimuth,
+            }),
+            Err(_) if header_allowed => {}
+            _ => {
+                return Err(invalid(format!(
+                    "line {} is not time,eccentricity,obliquity,axis_azimuth",
+                    number + 1
+                )));
+            }
+        }
+        header_allowed = false;
+    }
+    Ok(series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(time: Float, eccentricity: Float) -> Sample {
+        Sample {
+            time,
+            eccentricity,
+            obliquity: 20. + time / 100.,
+            axis_azimuth: -time,
+        }
+    }
+
+    #[test]
+    fn interpolation_hits_the_knots_and_lies_between_them() {
+        let series = [sample(0., 0.01), sample(100., 0.05), sample(300., 0.03)];
+        for knot in series {
+            assert_eq!(interpolate(&series, knot.time), knot);
+        }
+        let between = interpolate(&series, 200.);
+        assert_eq!(between, sample(200., 0.04));
+        // Held beyond the ends
+        assert_eq!(interpolate(&series, -50.), series[0]);
+        assert_eq!(interpolate(&series, 1e6), series[2]);
+    }
+
+    #[test]
+    fn cycles_repeat_after_their_period() {
+        let cycle = Cycle {
+            element: Element::Obliquity,
+            amplitude: 1.5,
+            period: 41_000.,
+            phase: 30.,
+        };
+        for years in [0., 1234., 20_500.] {
+            let (once, again) = (cycle.value(years), cycle.value(years + cycle.period));
+            assert!((once - again).abs() < 1e-3, "{once} != {again}");
+        }
+        assert!((cycle.value(0.) - 0.75).abs() < 1e-5);
+    }
+
+    #[test]
+    fn malformed_tables_are_rejected() {
+        let path =
+            std::env::temp_dir().join(format!("malformed-forcing-{}.csv", std::process::id()));
+        fs::write(
+            &path,
+            "time,eccentricity,obliquity,axis_azimuth\n0,0.01,23,0\n1000,0.02\n",
+        )
+        .unwrap();
+        let result = read_series(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(ConfigError::Invalid { field, reason }) => {
+                assert_eq!(field, "forcing.table");
+                assert!(reason.contains("line 3"), "{reason}");
+            }
+            other => panic!("expected a malformed table, got {other:?}"),
+        }
+    }
+}
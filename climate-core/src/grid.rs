@@ -0,0 +1,172 @@
+use serde::{Deserialize, Serialize};
+
+use crate::float::{Float, Vec3, consts::PI};
+
+/// The longitude–latitude grid the temperature field lives on.
+///
+/// Fields are indexed `[[x, y]]`. Column `x` spans longitudes
+/// `[x, x + 1) * dlon`, measured from the local +x axis towards +y. Row `y`
+/// spans colatitudes `[y, y + 1) * dcolat`, measured from the +z pole, so row
+/// 0 touches the +z pole and the last row the -z pole. This is the same
+/// layout as the UV sphere the map is drawn on, where `u` follows `x` and `v`
+/// follows `y`.
+///
+/// All positions are cell centres, so no cell sits exactly on a pole.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grid {
+    pub width: usize,
+    pub height: usize, // Width should be about PI times larger than height!
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid {
+            width: 161,
+            height: 51,
+        }
+    }
+}
+
+/// The four cells sharing a face with a cell. Longitude wraps around; there
+/// is nothing beyond the polar rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Neighbors {
+    pub east: (usize, usize),
+    pub west: (usize, usize),
+    pub north: Option<(usize, usize)>,
+    pub south: Option<(usize, usize)>,
+}
+
+impl Grid {
+    pub fn from_dim((width, height): (usize, usize)) -> Self {
+        Grid { width, height }
+    }
+
+    /// Shape of a field on this grid, `(width, height)`.
+    pub fn dim(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Longitude spacing
+    pub fn dlon(&self) -> Float {
+        2. * PI / self.width as Float
+    }
+
+    /// Colatitude spacing
+    pub fn dcolat(&self) -> Float {
+        PI / self.height as Float
+    }
+
+    /// Longitude of the centre of column `x`
+    pub fn lon(&self, x: usize) -> Float {
+        (x as Float + 0.5) * self.dlon()
+    }
+
+    /// Colatitude of the centre of row `y`, 0 at the +z pole
+    pub fn colat(&self, y: usize) -> Float {
+        (y as Float + 0.5) * self.dcolat()
+    }
+
+    /// Latitude of the centre of row `y`, positive towards +z
+    pub fn lat(&self, y: usize) -> Float {
+        PI / 2. - self.colat(y)
+    }
+
+    /// Colatitude of the boundary between rows `y - 1` and `y`, for `y` in
+    /// `0..=height`.
+    pub fn colat_edge(&self, y: usize) -> Float {
+        y as Float * self.dcolat()
+    }
+
+    /// Area of a cell in row `y` on the unit sphere. The areas of all cells
+    /// add up to 4 pi.
+    pub fn area(&self, y: usize) -> Float {
+        self.dlon() * (self.colat_edge(y).cos() - self.colat_edge(y + 1).cos())
+    }
+
+    /// Nominal cell area used to scale the radiation
+    pub fn da(&self) -> Float {
+        self.dcolat() * self.dlon() // This isn't quite right
+    }
+
+    /// Outward unit normal at the centre of cell `(x, y)`, in the planet's
+    /// local frame.
+    pub fn normal(&self, x: usize, y: usize) -> Vec3 {
+        let (lon, colat) = (self.lon(x), self.colat(y));
+        Vec3::new(
+            colat.sin() * lon.cos(),
+            colat.sin() * lon.sin(),
+            colat.cos(),
+        )
+    }
+
+    /// The cell containing the direction `v` in the planet's local frame.
+    pub fn cell_at(&self, v: Vec3) -> (usize, usize) {
+        let v = v.normalize();
+        let lon = v.y.atan2(v.x).rem_euclid(2. * PI);
+        let colat = v.z.clamp(-1., 1.).acos();
+        (
+            ((lon / self.dlon()) as usize).min(self.width - 1),
+            ((colat / self.dcolat()) as usize).min(self.height - 1),
+        )
+    }
+
+    pub fn east(&self, x: usize) -> usize {
+        if x == self.width - 1 { 0 } else { x + 1 }
+    }
+
+    pub fn west(&self, x: usize) -> usize {
+        if x == 0 { self.width - 1 } else { x - 1 }
+    }
+
+    /// Row towards the +z pole, if any
+    pub fn north(&self, y: usize) -> Option<usize> {
+        y.checked_sub(1)
+    }
+
+    /// Row towards the -z pole, if any
+    pub fn south(&self, y: usize) -> Option<usize> {
+        (y + 1 < self.height).then_some(y + 1)
+    }
+
+    pub fn neighbors(&self, x: usize, y: usize) -> Neighbors {
+        Neighbors {
+            east: (self.east(x), y),
+            west: (self.west(x), y),
+            north: self.north(y).map(|n| (x, n)),
+            south: self.south(y).map(|s| (x, s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float::widen;
+
+    #[test]
+    fn cell_areas_cover_the_sphere() {
+        for grid in [
+            Grid::default(),
+            Grid::from_dim((3, 3)),
+            Grid::from_dim((64, 4)),
+        ] {
+            let total: f64 = (0..grid.height)
+                .map(|y| grid.width as f64 * widen(grid.area(y)))
+                .sum();
+            assert!(
+                (total - 4. * std::f64::consts::PI).abs() < 1e-5,
+                "{grid:?} covers {total}"
+            );
+        }
+    }
+}
@@ -0,0 +1,658 @@
+//! One-step time integrators for `dy/dt = f(y)` on a field.
+//!
+//! A right-hand side is anything implementing [`Rhs`], which includes every
+//! `FnMut(&Array2<Float>) -> Array2<Float>` closure, so parameters such as the
+//! diffusivity can simply be captured. [`Method`] names each integrator so a
+//! process can pick one from the config.
+//!
+//! The integrators update the field in place and keep their stages in a
+//! [`Workspace`], so once it is warmed up a step allocates nothing beyond
+//! what the right-hand side itself does. The stage arithmetic runs on all
+//! cores.
+
+use std::{fmt, mem};
+
+use ndarray::{Array2, Zip, parallel::prelude::*};
+use serde::{Deserialize, Serialize};
+
+use crate::{config::AdaptiveConfig, float::Float};
+
+/// Cap on the fixed-point iterations of the default [`Rhs::solve`]
+const FIXED_POINT_ITERATIONS: usize = 50;
+
+/// Right-hand side `f` of `dy/dt = f(y)`.
+pub trait Rhs {
+    fn eval(&mut self, y: &Array2<Float>) -> Array2<Float>;
+
+    /// [`Rhs::eval`] into an existing array of the same shape. Processes on
+    /// the hot path override it to avoid the allocation.
+    fn eval_into(&mut self, y: &Array2<Float>, out: &mut Array2<Float>) {
+        out.assign(&self.eval(y));
+    }
+
+    /// Solve `y' = y + h((1 - θ) f(y) + θ f(y'))` for `y'`, in place.
+    ///
+    /// The default iterates to a fixed point, which only converges while
+    /// `θh` times the Lipschitz constant of `f` stays below 1. Stiff
+    /// processes should override it with a direct solve.
+    fn solve(&mut self, y: &mut Array2<Float>, h: Float, theta: Float) {
+        let mut known = y.clone();
+        if theta < 1. {
+            known.scaled_add((1. - theta) * h, &self.eval(y));
+        }
+
+        for _ in 0..FIXED_POINT_ITERATIONS {
+            let mut update = known.clone();
+            update.scaled_add(theta * h, &self.eval(y));
+            let change = max_abs_diff(&update, y);
+            *y = update;
+            if change <= Float::EPSILON * max_abs(y) {
+                break;
+            }
+        }
+    }
+}
+
+impl<F: FnMut(&Array2<Float>) -> Array2<Float>> Rhs for F {
+    fn eval(&mut self, y: &Array2<Float>) -> Array2<Float> {
+        self(y)
+    }
+}
+
+/// Result of a single step.
+#[derive(Clone, Debug)]
+pub struct Step {
+    pub y: Array2<Float>,
+    /// Estimated local error in the max norm, from embedded pairs only
+    pub error: Option<Float>,
+}
+
+/// Stage buffers reused from step to step.
+#[derive(Clone, Debug, Default)]
+pub struct Workspace {
+    stages: Vec<Array2<Float>>,
+    /// Argument of the next stage evaluation
+    arg: Array2<Float>,
+    /// Candidate solution of an adaptive step
+    trial: Array2<Float>,
+}
+
+impl Workspace {
+    /// `n` stage buffers and the argument buffer, all shaped like `dim`.
+    fn buffers(
+        &mut self,
+        n: usize,
+        dim: (usize, usize),
+    ) -> (&mut [Array2<Float>], &mut Array2<Float>) {
+        if self.arg.dim() != dim {
+            self.stages.clear();
+            self.arg = Array2::zeros(dim);
+        }
+        while self.stages.len() < n {
+            self.stages.push(Array2::zeros(dim));
+        }
+        (&mut self.stages[..n], &mut self.arg)
+    }
+}
+
+pub trait Integrator {
+    /// Advance `y` by `h` under `f` in place, returning the estimated local
+    /// error in the max norm for embedded pairs.
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        work: &mut Workspace,
+    ) -> Option<Float>;
+
+    /// Advance a copy of `y` by `h` under `f`.
+    fn step<R: Rhs + ?Sized>(&self, f: &mut R, y: &Array2<Float>, h: Float) -> Step {
+        let mut y = y.clone();
+        let error = self.advance(f, &mut y, h, &mut Workspace::default());
+        Step { y, error }
+    }
+}
+
+/// Forward Euler, first order.
+#[derive(Clone, Copy, Debug)]
+pub struct Euler;
+
+impl Integrator for Euler {
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        work: &mut Workspace,
+    ) -> Option<Float> {
+        let ([k1], _) = work.buffers(1, y.dim()) else {
+            unreachable!()
+        };
+        f.eval_into(y, k1);
+        accumulate(y, h, &[(1., &*k1)]);
+        None
+    }
+}
+
+/// Heun's method, the explicit trapezoidal rule; second order.
+#[derive(Clone, Copy, Debug)]
+pub struct Heun;
+
+impl Integrator for Heun {
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        work: &mut Workspace,
+    ) -> Option<Float> {
+        let ([k1, k2], arg) = work.buffers(2, y.dim()) else {
+            unreachable!()
+        };
+        f.eval_into(y, k1);
+        combine(arg, y, h, &[(1., &*k1)]);
+        f.eval_into(arg, k2);
+        accumulate(y, h, &[(0.5, &*k1), (0.5, &*k2)]);
+        None
+    }
+}
+
+/// The classic fourth order Runge–Kutta method.
+#[derive(Clone, Copy, Debug)]
+pub struct Rk4;
+
+impl Integrator for Rk4 {
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        work: &mut Workspace,
+    ) -> Option<Float> {
+        let ([k1, k2, k3, k4], arg) = work.buffers(4, y.dim()) else {
+            unreachable!()
+        };
+        f.eval_into(y, k1);
+        combine(arg, y, h, &[(0.5, &*k1)]);
+        f.eval_into(arg, k2);
+        combine(arg, y, h, &[(0.5, &*k2)]);
+        f.eval_into(arg, k3);
+        combine(arg, y, h, &[(1., &*k3)]);
+        f.eval_into(arg, k4);
+        accumulate(
+            y,
+            h,
+            &[
+                (1. / 6., &*k1),
+                (1. / 3., &*k2),
+                (1. / 3., &*k3),
+                (1. / 6., &*k4),
+            ],
+        );
+        None
+    }
+}
+
+/// Dormand–Prince 5(4): advances with the fifth order solution and uses the
+/// embedded fourth order one for the error estimate.
+#[derive(Clone, Copy, Debug)]
+pub struct Rk45;
+
+impl Integrator for Rk45 {
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        work: &mut Workspace,
+    ) -> Option<Float> {
+        let ([k1, k2, k3, k4, k5, k6, k7], arg) = work.buffers(7, y.dim()) else {
+            unreachable!()
+        };
+        f.eval_into(y, k1);
+        combine(arg, y, h, &[(1. / 5., &*k1)]);
+        f.eval_into(arg, k2);
+        combine(arg, y, h, &[(3. / 40., &*k1), (9. / 40., &*k2)]);
+        f.eval_into(arg, k3);
+        combine(
+            arg,
+            y,
+            h,
+            &[(44. / 45., &*k1), (-56. / 15., &*k2), (32. / 9., &*k3)],
+        );
+        f.eval_into(arg, k4);
+        combine(
+            arg,
+            y,
+            h,
+            &[
+                (19372. / 6561., &*k1),
+                (-25360. / 2187., &*k2),
+                (64448. / 6561., &*k3),
+                (-212. / 729., &*k4),
+            ],
+        );
+        f.eval_into(arg, k5);
+        combine(
+            arg,
+            y,
+            h,
+            &[
+                (9017. / 3168., &*k1),
+                (-355. / 33., &*k2),
+                (46732. / 5247., &*k3),
+                (49. / 176., &*k4),
+                (-5103. / 18656., &*k5),
+            ],
+        );
+        f.eval_into(arg, k6);
+        accumulate(
+            y,
+            h,
+            &[
+                (35. / 384., &*k1),
+                (500. / 1113., &*k3),
+                (125. / 192., &*k4),
+                (-2187. / 6784., &*k5),
+                (11. / 84., &*k6),
+            ],
+        );
+        f.eval_into(y, k7);
+
+        // Difference between the fifth and fourth order weights
+        arg.fill(0.);
+        accumulate(
+            arg,
+            h,
+            &[
+                (71. / 57600., &*k1),
+                (-71. / 16695., &*k3),
+                (71. / 1920., &*k4),
+                (-17253. / 339200., &*k5),
+                (22. / 525., &*k6),
+                (-1. / 40., &*k7),
+            ],
+        );
+        Some(max_abs(arg))
+    }
+}
+
+/// The θ-method `y' = y + h((1 - θ) f(y) + θ f(y'))`: backward Euler for
+/// θ = 1, Crank–Nicolson for θ = 0.5. It is only as implicit as the
+/// right-hand side's [`Rhs::solve`].
+#[derive(Clone, Copy, Debug)]
+pub struct Theta(pub Float);
+
+impl Integrator for Theta {
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        _work: &mut Workspace,
+    ) -> Option<Float> {
+        f.solve(y, h, self.0);
+        None
+    }
+}
+
+/// Integrator choice as it appears in the config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Method {
+    Euler,
+    Heun,
+    Rk4,
+    Rk45,
+    /// Unconditionally stable for diffusion and strongly damping, first order
+    BackwardEuler,
+    /// Unconditionally stable for diffusion and second order, but the
+    /// shortest wavelengths ring instead of decaying when the step is far
+    /// above the explicit limit
+    CrankNicolson,
+}
+
+impl Method {
+    /// Whether the method is free of an explicit stability limit.
+    pub fn is_implicit(self) -> bool {
+        matches!(self, Method::BackwardEuler | Method::CrankNicolson)
+    }
+
+    /// Whether [`Integrator::advance`] returns an error estimate, as
+    /// adaptive stepping needs.
+    pub fn is_embedded(self) -> bool {
+        matches!(self, Method::Rk45)
+    }
+}
+
+impl Integrator for Method {
+    fn advance<R: Rhs + ?Sized>(
+        &self,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        h: Float,
+        work: &mut Workspace,
+    ) -> Option<Float> {
+        match self {
+            Method::Euler => Euler.advance(f, y, h, work),
+            Method::Heun => Heun.advance(f, y, h, work),
+            Method::Rk4 => Rk4.advance(f, y, h, work),
+            Method::Rk45 => Rk45.advance(f, y, h, work),
+            Method::BackwardEuler => Theta(1.).advance(f, y, h, work),
+            Method::CrankNicolson => Theta(0.5).advance(f, y, h, work),
+        }
+    }
+}
+
+/// Smallest step an [`Adaptive`] controller takes, as a fraction of the span.
+/// A step still rejected at this size is taken anyway, so a config too stiff
+/// for its tolerance runs less accurately instead of stalling.
+pub const MIN_STEP: Float = 1e-3;
+
+/// Step size controller for embedded pairs.
+///
+/// It carries the step size it would try next from one span to the next, so
+/// it is part of the model state and restarts continue exactly.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Adaptive {
+    next: Option<Float>,
+    stats: StepStats,
+}
+
+impl Adaptive {
+    /// Advance `y` over `span` in as many steps as the tolerance allows.
+    ///
+    /// Panics if `integrator` gives no error estimate. Steps are never cut
+    /// below [`MIN_STEP`] of the span; one that misses the tolerance even then
+    /// is accepted and counted in [`StepStats::forced`].
+    pub fn integrate<I: Integrator, R: Rhs + ?Sized>(
+        &mut self,
+        settings: &AdaptiveConfig,
+        integrator: &I,
+        f: &mut R,
+        y: &mut Array2<Float>,
+        span: Float,
+        work: &mut Workspace,
+    ) {
+        let mut trial = mem::take(&mut work.trial);
+        let mut h = self.next.unwrap_or(span);
+        let mut t = 0.;
+
+        while t < span {
+            let remaining = span - t;
+            let clipped = h >= remaining;
+            let h_try = h.min(remaining);
+
+            if trial.dim() == y.dim() {
+                trial.assign(y);
+            } else {
+                trial = y.clone();
+            }
+            let error = integrator
+                .advance(f, &mut trial, h_try, work)
+                .expect("adaptive stepping needs an integrator with an error estimate");
+
+            // The embedded estimate is fourth order, so error ~ h^5
+            let factor = if error.is_finite() {
+                (settings.safety * (settings.tolerance / error).powf(0.2))
+                    .clamp(settings.min_shrink, settings.max_growth)
+            } else {
+                settings.min_shrink
+            };
+
+            let next = (h_try * factor).max(span * MIN_STEP);
+            let met = error <= settings.tolerance;
+            let forced = !met && h_try <= span * MIN_STEP;
+            if met || forced {
+                mem::swap(y, &mut trial);
+                // The last step is often clipped short; don't let that
+                // shrink the next span's first step
+                h = if clipped { h.max(next) } else { next };
+                t = if clipped { span } else { t + h_try };
+                self.stats.accept(h_try, forced);
+            } else {
+                h = next;
+                self.stats.reject(h_try);
+            }
+        }
+
+        work.trial = trial;
+        self.next = Some(h);
+    }
+
+    pub fn stats(&self) -> &StepStats {
+        &self.stats
+    }
+}
+
+/// Running tally of the steps an [`Adaptive`] controller tried.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct StepStats {
+    pub accepted: u64,
+    pub rejected: u64,
+    /// Steps accepted at the smallest size although they missed the
+    /// tolerance
+    pub forced: u64,
+    /// Smallest and largest accepted step
+    pub min_step: Float,
+    pub max_step: Float,
+    pub last_step: Float,
+    /// Most recent step thrown away, 0 before the first rejection
+    pub last_rejected: Float,
+}
+
+impl Default for StepStats {
+    fn default() -> Self {
+        StepStats {
+            accepted: 0,
+            rejected: 0,
+            forced: 0,
+            min_step: Float::INFINITY,
+            max_step: 0.,
+            last_step: 0.,
+            last_rejected: 0.,
+        }
+    }
+}
+
+impl StepStats {
+    fn accept(&mut self, h: Float, forced: bool) {
+        self.accepted += 1;
+        self.forced += u64::from(forced);
+        self.min_step = self.min_step.min(h);
+        self.max_step = self.max_step.max(h);
+        self.last_step = h;
+    }
+
+    fn reject(&mut self, h: Float) {
+        self.rejected += 1;
+        self.last_rejected = h;
+    }
+}
+
+impl fmt::Display for StepStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} accepted in {:.3e}..{:.3e} (last {:.3e}), {} rejected (last {:.3e}), {} forced",
+            self.accepted,
+            self.min_step,
+            self.max_step,
+            self.last_step,
+            self.rejected,
+            self.last_rejected,
+            self.forced
+        )
+    }
+}
+
+/// `out = base + h Σ a k`
+fn combine(
+    out: &mut Array2<Float>,
+    base: &Array2<Float>,
+    h: Float,
+    terms: &[(Float, &Array2<Float>)],
+) {
+    out.assign(base);
+    accumulate(out, h, terms);
+}
+
+/// `y += h Σ a k`
+fn accumulate(y: &mut Array2<Float>, h: Float, terms: &[(Float, &Array2<Float>)]) {
+    for &(a, k) in terms {
+        let a = h * a;
+        Zip::from(&mut *y).and(k).par_for_each(|y, &k| *y += a * k);
+    }
+}
+
+fn max_abs(y: &Array2<Float>) -> Float {
+    y.par_iter().map(|v| v.abs()).reduce(|| 0., Float::max)
+}
+
+fn max_abs_diff(a: &Array2<Float>, b: &Array2<Float>) -> Float {
+    Zip::from(a)
+        .and(b)
+        .par_fold(|| 0., |max: Float, a, b| max.max((a - b).abs()), Float::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float::widen;
+
+    /// The harmonic oscillator `x' = -v, v' = x`, which keeps its amplitude
+    /// so the error builds up over many steps instead of decaying away.
+    fn oscillator(y: &Array2<Float>) -> Array2<Float> {
+        let (x, v) = (y[[0, 0]], y[[1, 0]]);
+        Array2::from_shape_vec((2, 1), vec![-v, x]).unwrap()
+    }
+
+    fn start() -> Array2<Float> {
+        Array2::from_shape_vec((2, 1), vec![1., 0.]).unwrap()
+    }
+
+    /// Distance from the exact solution after `span` in `steps` steps.
+    fn error(method: Method, span: Float, steps: usize) -> f64 {
+        let mut y = start();
+        for _ in 0..steps {
+            y = method.step(&mut oscillator, &y, span / steps as Float).y;
+        }
+        let span = widen(span);
+        (widen(y[[0, 0]]) - span.cos()).hypot(widen(y[[1, 0]]) - span.sin())
+    }
+
+    #[test]
+    fn halving_the_step_shows_the_order() {
+        // Steps small enough for the leading error term to dominate, and
+        // large enough for it to stay clear of round-off in single precision
+        for (method, order, steps) in [
+            (Method::Euler, 1., 1000),
+            (Method::Heun, 2., 100),
+            (Method::Rk4, 4., 40),
+            (Method::Rk45, 5., 20),
+        ] {
+            let measured = (error(method, 16., steps) / error(method, 16., 2 * steps)).log2();
+            assert!(
+                (measured - order).abs() < 0.3,
+                "{method:?} converges with order {measured}"
+            );
+        }
+    }
+
+    #[test]
+    fn rk45_error_estimate_is_fifth_order() {
+        let estimate = |h| {
+            Method::Rk45
+                .step(&mut oscillator, &start(), h)
+                .error
+                .unwrap()
+        };
+        let measured = widen(estimate(0.4) / estimate(0.2)).log2();
+        assert!((measured - 5.).abs() < 0.3, "estimate of order {measured}");
+    }
+
+    /// The oscillator with a clock in the third cell.
+    fn clocked(y: &Array2<Float>) -> Array2<Float> {
+        let (x, v) = (y[[0, 0]], y[[1, 0]]);
+        Array2::from_shape_vec((3, 1), vec![-v, x, 1.]).unwrap()
+    }
+
+    /// Run `spans` spans of `span` adaptively from the start of the clocked
+    /// oscillator, checking that each one ends exactly on time.
+    fn integrate(
+        settings: &AdaptiveConfig,
+        span: Float,
+        spans: usize,
+    ) -> (Array2<Float>, StepStats) {
+        let mut adaptive = Adaptive::default();
+        let mut y = Array2::from_shape_vec((3, 1), vec![1., 0., 0.]).unwrap();
+        let mut work = Workspace::default();
+        for i in 1..=spans {
+            adaptive.integrate(
+                settings,
+                &Method::Rk45,
+                &mut clocked,
+                &mut y,
+                span,
+                &mut work,
+            );
+            // The clock picks up a rounding error per step
+            let end = span * i as Float;
+            let steps = adaptive.stats().accepted as Float;
+            assert!(
+                (y[[2, 0]] - end).abs() <= steps * Float::EPSILON * end,
+                "span {i} ended at {} instead of {end}",
+                y[[2, 0]]
+            );
+        }
+        (y, *adaptive.stats())
+    }
+
+    #[test]
+    fn adaptive_steps_end_on_the_span() {
+        let settings = AdaptiveConfig {
+            tolerance: 1e-5,
+            ..AdaptiveConfig::default()
+        };
+        let (y, stats) = integrate(&settings, 2.5, 4);
+        assert!((widen(y[[0, 0]]) - 10f64.cos()).abs() < 1e-3);
+        assert_eq!(stats.forced, 0);
+    }
+
+    #[test]
+    fn steps_missing_the_tolerance_are_retried_smaller() {
+        let settings = AdaptiveConfig {
+            tolerance: 1e-5,
+            ..AdaptiveConfig::default()
+        };
+        // The first step tries the whole span, far too long
+        let (_, stats) = integrate(&settings, 2.5, 1);
+        assert!(stats.rejected > 0);
+        assert!(stats.accepted > 1);
+        assert!(stats.last_rejected > stats.min_step);
+        assert!(stats.max_step < 2.5);
+    }
+
+    #[test]
+    fn steps_within_the_tolerance_are_accepted_at_once() {
+        let (_, stats) = integrate(&AdaptiveConfig::default(), 1e-2, 1);
+        assert_eq!((stats.accepted, stats.rejected), (1, 0));
+        assert_eq!(stats.max_step, 1e-2);
+    }
+
+    #[test]
+    fn unreachable_tolerance_is_forced_through() {
+        let settings = AdaptiveConfig {
+            tolerance: 1e-30,
+            ..AdaptiveConfig::default()
+        };
+        let (_, stats) = integrate(&settings, 1., 1);
+        assert!(stats.forced > 0);
+        // None shorter than the smallest step, but for a clipped last one
+        assert!(
+            stats.accepted <= (1. / MIN_STEP).ceil() as u64 + 1,
+            "{stats}"
+        );
+    }
+}
@@ -0,0 +1,25 @@
+//! Bevy-free climate model.
+//!
+//! Everything needed to advance the planet's temperature field lives here as
+//! plain Rust types, so the physics can be driven from the `sim` frontend,
+//! batch tools or tests alike.
+
+pub mod checkpoint;
+pub mod config;
+pub mod coupling;
+pub mod diffusion;
+pub mod energy_diff;
+pub mod float;
+pub mod forcing;
+pub mod grid;
+pub mod integrator;
+pub mod model;
+pub mod orbit;
+pub mod temp;
+
+pub use config::SimConfig;
+pub use float::Float;
+pub use grid::Grid;
+pub use model::Model;
+pub use orbit::{Orbit, OrbitState};
+pub use temp::TempMap;
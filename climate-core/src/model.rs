@@ -0,0 +1,514 @@
+use std::iter;
+
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{OrbitConfig, PlanetConfig, SimConfig},
+    coupling::{Coupled, Process, Splitting},
+    diffusion::Diffusion,
+    energy_diff::{BlackBody, Insolation, Occluder, Source},
+    float::{Float, Quat, Vec3},
+    integrator::{Adaptive, Rhs, StepStats, Workspace},
+    orbit::{Orbit, OrbitState},
+    temp::TempMap,
+};
+
+/// The full climate model of a single planet: its temperature field, its
+/// orbit and its orientation in space, and those of its moons.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Model {
+    config: SimConfig,
+    surface: Surface,
+    /// One per moon of the config, in its order
+    moons: Vec<Surface>,
+    orbit: Orbit,
+    /// Derived from the config on the first step
+    #[serde(skip)]
+    diffusion: Option<Diffusion>,
+    #[serde(skip)]
+    insolation: Option<Insolation>,
+    /// Buffers reused from step to step
+    #[serde(skip)]
+    work: Workspace,
+}
+
+/// The climate of one body: its temperature field and everything that
+/// carries over from one step of it to the next.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Surface {
+    temp: TempMap,
+    /// Temperature of the gray atmosphere layer, if there is one
+    atmosphere: Option<TempMap>,
+    /// Step size control of adaptive diffusion
+    adaptive: Adaptive,
+    /// Buffer reused from step to step
+    #[serde(skip)]
+    heating: Array2<Float>,
+    /// The surface and the atmosphere stacked for radiating together, and
+    /// the stages of that, kept apart from diffusion's buffers as they are
+    /// twice the size
+    #[serde(skip)]
+    stacked: Array2<Float>,
+    #[serde(skip)]
+    radiation_work: Workspace,
+}
+
+/// Where a body is, how big it is and how it is turned.
+#[derive(Clone, Copy, Debug)]
+struct Placement {
+    position: Vec3,
+    radius: Float,
+    rotation: Quat,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Model::new(SimConfig::default())
+    }
+}
+
+impl Model {
+    pub fn new(config: SimConfig) -> Self {
+        let surface = Surface::new(&config, config.atmosphere.is_some());
+        // The atmosphere is the planet's, the moons radiate straight to space
+        let moons = config
+            .moons
+            .iter()
+            .map(|_| Surface::new(&config, false))
+            .collect();
+        Model {
+            config,
+            surface,
+            moons,
+            orbit: Orbit::default(),
+            diffusion: None,
+            insolation: None,
+            work: Workspace::default(),
+        }
+    }
+
+    /// Advance the model by `dt` simulated seconds.
+    ///
+    /// The clock moves first, then the processes run on the planet and every
+    /// moon under the configured splitting, with the stars, the bodies and
+    /// their orientations held where they ended up.
+    pub fn step(&mut self, dt: Float) {
+        self.orbit.advance(dt);
+        let placements = self.placements();
+        let sources: Vec<_> = placements
+            .iter()
+            .map(|placement| self.sources_at(placement.position))
+            .collect();
+
+        let config = &self.config;
+        let insolation = self
+            .insolation
+            .get_or_insert_with(|| Insolation::new(&config.flux, &config.grid));
+        let surfaces = iter::once(&mut self.surface).chain(&mut self.moons);
+        for (i, surface) in surfaces.enumerate() {
+            // Every other body may eclipse this one
+            let occluders: Vec<_> = placements
+                .iter()
+                .enumerate()
+                .filter(|&(j, _)| j != i)
+                .map(|(_, other)| Occluder {
+                    position: other.position,
+                    radius: other.radius,
+                })
+                .collect();
+            let placement = placements[i];
+            if surface.heating.dim() != config.grid.dim() {
+                surface.heating = Array2::zeros(config.grid.dim());
+            }
+            insolation.apply_eclipsed_into(
+                placement.position,
+                placement.radius,
+                placement.rotation,
+                &sources[i],
+                &occluders,
+                &mut surface.heating,
+            );
+            surface.step(config, &mut self.diffusion, &mut self.work, dt);
+        }
+    }
+
+    /// The planet followed by its moons.
+    fn placements(&self) -> Vec<Placement> {
+        let planet = Placement {
+            position: self.position(),
+            radius: self.config.planet.radius,
+            rotation: self.rotation(),
+        };
+        let moons = self
+            .config
+            .moons
+            .iter()
+            .enumerate()
+            .map(|(i, moon)| Placement {
+                position: self.moon_position(i),
+                radius: moon.body.radius,
+                rotation: self.moon_rotation(i),
+            });
+        iter::once(planet).chain(moons).collect()
+    }
+
+    pub fn config(&self) -> &SimConfig {
+        &self.config
+    }
+
+    pub fn temperature(&self) -> &TempMap {
+        &self.surface.temp
+    }
+
+    /// Temperature field of the atmosphere layer, if there is one.
+    pub fn atmosphere_temperature(&self) -> Option<&TempMap> {
+        self.surface.atmosphere.as_ref()
+    }
+
+    /// Temperature field of moon `i`.
+    pub fn moon_temperature(&self, i: usize) -> &TempMap {
+        &self.moons[i].temp
+    }
+
+    pub fn orbit(&self) -> &Orbit {
+        &self.orbit
+    }
+
+    pub fn time(&self) -> Float {
+        self.orbit.time
+    }
+
+    pub fn position(&self) -> Vec3 {
+        let (orbit, _) = self.elements();
+        let center = match orbit.host {
+            Some(host) => self.star_positions()[host],
+            None => Vec3::ZERO,
+        };
+        center + self.orbit.position(&orbit)
+    }
+
+    /// Where every star is now, in the order of the config.
+    pub fn star_positions(&self) -> Vec<Vec3> {
+        let mut positions: Vec<Vec3> = Vec::with_capacity(self.config.stars.len());
+        for star in &self.config.stars {
+            let position = match &star.orbit {
+                Some(orbit) => {
+                    let center = orbit.host.map_or(Vec3::ZERO, |host| positions[host]);
+                    center + self.orbit.position(orbit)
+                }
+                None => Vec3::ZERO,
+            };
+            positions.push(position);
+        }
+        positions
+    }
+
+    /// Where moon `i` is now.
+    pub fn moon_position(&self, i: usize) -> Vec3 {
+        self.position() + self.orbit.position(&self.config.moons[i].orbit)
+    }
+
+    /// Orientation of moon `i`, turned about its north pole by the time
+    /// since the start.
+    pub fn moon_rotation(&self, i: usize) -> Quat {
+        let moon = &self.config.moons[i];
+        moon.body.axis(&moon.orbit) * Quat::from_rotation_z(moon.body.spin_angle(self.time()))
+    }
+
+    /// Every star as seen from the planet now.
+    pub fn sources(&self) -> Vec<Source> {
+        self.sources_at(self.position())
+    }
+
+    /// Every star as seen from `position` now.
+    pub fn sources_at(&self, position: Vec3) -> Vec<Source> {
+        let years = self.forcing_years();
+        self.config
+            .stars
+            .iter()
+            .zip(self.star_positions())
+            .map(|(star, star_position)| Source {
+                position: star_position,
+                radius: star.radius,
+                irradiance: star.irradiance(position.distance(star_position), years),
+            })
+            .collect()
+    }
+
+    /// Full orbital kinematics at the current time, relative to the body the
+    /// planet orbits.
+    pub fn orbit_state(&self) -> OrbitState {
+        let (orbit, _) = self.elements();
+        self.orbit.state(&orbit)
+    }
+
+    /// The orbit and the planet's spin axis in effect now, with the
+    /// long-term forcing applied.
+    pub fn elements(&self) -> (OrbitConfig, PlanetConfig) {
+        let (mut orbit, mut planet) = (self.config.orbit.clone(), self.config.planet.clone());
+        let forcing = &self.config.forcing;
+        if forcing.is_active() {
+            forcing.apply(self.forcing_years(), &mut orbit, &mut planet);
+        }
+        (orbit, planet)
+    }
+
+    /// Irradiance from all stars together at their current distances.
+    pub fn irradiance(&self) -> Float {
+        self.sources().iter().map(|source| source.irradiance).sum()
+    }
+
+    /// Forcing years since the start, see [`crate::forcing`]. They count
+    /// the system's years, so every planet of it agrees on them.
+    pub fn forcing_years(&self) -> Float {
+        self.config.forcing.years(self.time(), self.config.year())
+    }
+
+    /// Orientation of the planet, turned about its north pole by the time
+    /// since the start.
+    pub fn rotation(&self) -> Quat {
+        let (orbit, planet) = self.elements();
+        planet.axis(&orbit) * Quat::from_rotation_z(planet.spin_angle(self.time()))
+    }
+
+    /// Steps taken by adaptive diffusion, if it is enabled.
+    pub fn diffusion_stats(&self) -> Option<&StepStats> {
+        self.config
+            .diffusion
+            .adaptive
+            .is_some()
+            .then(|| self.surface.adaptive.stats())
+    }
+}
+
+impl Surface {
+    /// A body at rest, with an atmosphere layer if `atmosphere` is set.
+    fn new(config: &SimConfig, atmosphere: bool) -> Self {
+        let mut temp = TempMap::zeros(&config.grid);
+        if let Some(seed) = config.initial.seed {
+            temp.perturb(seed, config.initial.perturbation);
+        }
+        Surface {
+            temp,
+            atmosphere: atmosphere.then(|| TempMap::zeros(&config.grid)),
+            adaptive: Adaptive::default(),
+            heating: Array2::zeros((0, 0)),
+            stacked: Array2::zeros((0, 0)),
+            radiation_work: Workspace::default(),
+        }
+    }
+
+    /// Run the processes for `dt` simulated seconds with the insolation
+    /// already in `heating`.
+    fn step(
+        &mut self,
+        config: &SimConfig,
+        diffusion: &mut Option<Diffusion>,
+        work: &mut Workspace,
+        dt: Float,
+    ) {
+        let order = &config.coupling.order;
+        match config.coupling.splitting {
+            Splitting::Lie => {
+                for &process in order {
+                    self.advance(process, dt, config, diffusion, work);
+                }
+            }
+            Splitting::Strang => {
+                let (&last, rest) = order.split_last().expect("validated to be non-empty");
+                for &process in rest {
+                    self.advance(process, dt / 2., config, diffusion, work);
+                }
+                self.advance(last, dt, config, diffusion, work);
+                for &process in rest.iter().rev() {
+                    self.advance(process, dt / 2., config, diffusion, work);
+                }
+            }
+            Splitting::Coupled => {
+                let black_body = BlackBody::new(&config.radiation, &config.grid);
+                let diffusion = diffusion
+                    .get_or_insert_with(|| Diffusion::new(&config.grid, config.diffusion.kappa));
+                let mut coupled = Coupled::new(
+                    diffusion,
+                    &self.heating,
+                    black_body,
+                    1. / config.planet_dt(),
+                );
+                integrate_heat_eq(
+                    &mut self.temp,
+                    &mut self.adaptive,
+                    work,
+                    config,
+                    &mut coupled,
+                    dt,
+                );
+            }
+        }
+    }
+
+    /// Run a single process for `h` simulated seconds.
+    ///
+    /// The heat sources are calibrated per physics tick, so they are scaled
+    /// by `h / planet_dt`.
+    fn advance(
+        &mut self,
+        process: Process,
+        h: Float,
+        config: &SimConfig,
+        diffusion: &mut Option<Diffusion>,
+        work: &mut Workspace,
+    ) {
+        let ticks = h / config.planet_dt();
+        match process {
+            Process::Diffusion => {
+                let diffusion = diffusion
+                    .get_or_insert_with(|| Diffusion::new(&config.grid, config.diffusion.kappa));
+                integrate_heat_eq(
+                    &mut self.temp,
+                    &mut self.adaptive,
+                    work,
+                    config,
+                    diffusion,
+                    h,
+                );
+            }
+            Process::Insolation => self.temp.add_heat(&self.heating, ticks),
+            Process::Radiation => match (&mut self.atmosphere, &config.atmosphere) {
+                (Some(atmosphere), Some(layer)) => self.temp.radiate_gray_atmosphere(
+                    atmosphere,
+                    config.radiation.scheme,
+                    ticks,
+                    &config.radiation,
+                    layer,
+                    &mut self.stacked,
+                    &mut self.radiation_work,
+                ),
+                _ => self.temp.radiate_black_body(
+                    config.radiation.scheme,
+                    ticks,
+                    &config.radiation,
+                    work,
+                ),
+            },
+        }
+    }
+}
+
+/// Integrate `f` over `h` with the diffusion scheme and its step control;
+/// diffusion is the stiff process that sets the pace.
+fn integrate_heat_eq<R: Rhs + ?Sized>(
+    temp: &mut TempMap,
+    adaptive: &mut Adaptive,
+    work: &mut Workspace,
+    config: &SimConfig,
+    f: &mut R,
+    h: Float,
+) {
+    let scheme = config.diffusion.scheme;
+    if let Some(settings) = &config.diffusion.adaptive {
+        temp.apply_heat_eq_adaptive(adaptive, settings, scheme, f, h, work);
+    } else {
+        let steps = config.diffusion.steps(&config.grid, h);
+        for _ in 0..steps {
+            temp.apply_heat_eq(scheme, f, h / steps as Float, work);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Grid,
+        config::{MoonConfig, StarEvolution, SystemPlanet},
+    };
+
+    fn small() -> SimConfig {
+        SimConfig {
+            grid: Grid {
+                width: 32,
+                height: 12,
+            },
+            ..SimConfig::default()
+        }
+    }
+
+    #[test]
+    fn irradiance_follows_the_inverse_square_of_the_distance() {
+        let mut config = small();
+        config.orbit.e = 0.3;
+        let OrbitConfig {
+            e,
+            per_time,
+            period_time,
+            ..
+        } = config.orbit;
+        let mut model = Model::new(config);
+        model.orbit.time = per_time;
+        let perihelion = model.irradiance();
+        model.orbit.time = per_time + period_time / 2.;
+        let aphelion = model.irradiance();
+        let expected = ((1. + e) / (1. - e)).powi(2);
+        assert!((perihelion / aphelion / expected - 1.).abs() < 1e-5);
+    }
+
+    /// Total insolation of the planet with a moon at `phase` of its orbit,
+    /// a half putting it between the planet and the star at the start.
+    fn insolation_with_moon(phase: Float) -> Float {
+        let mut config = small();
+        let mut moon = MoonConfig::default();
+        moon.orbit.per_time = phase * moon.orbit.period_time;
+        config.moons = vec![moon];
+        let mut model = Model::new(config);
+        model.step(1e-6);
+        model.surface.heating.sum()
+    }
+
+    #[test]
+    fn a_moon_in_front_of_the_star_shades_the_planet() {
+        let eclipsed = insolation_with_moon(0.5);
+        let clear = insolation_with_moon(0.);
+        assert!(eclipsed < 0.99 * clear, "{eclipsed} against {clear}");
+    }
+
+    #[test]
+    fn planets_share_the_system_clock() {
+        let mut config = small();
+        config.stars[0].evolution = Some(StarEvolution {
+            age: 0.,
+            reference_age: 50.,
+        });
+        config.forcing.acceleration = 10.;
+        let planet = |name: &str, period_time, a| SystemPlanet {
+            name: name.to_string(),
+            orbit: Some(OrbitConfig {
+                period_time,
+                a,
+                ..OrbitConfig::default()
+            }),
+            ..SystemPlanet::default()
+        };
+        config.planets = vec![planet("inner", 20., 5.), planet("outer", 90., 15.)];
+        config.validate().unwrap();
+
+        let mut models: Vec<_> = config
+            .planet_configs()
+            .into_iter()
+            .map(|(_, config)| Model::new(config))
+            .collect();
+        let dt = config.planet_dt();
+        for _ in 0..10 {
+            for model in &mut models {
+                model.step(dt);
+            }
+        }
+        let [inner, outer] = &models[..] else {
+            unreachable!()
+        };
+        assert_eq!(inner.forcing_years(), outer.forcing_years());
+        let luminosity = |model: &Model| model.config().stars[0].luminosity(model.forcing_years());
+        assert_eq!(luminosity(inner), luminosity(outer));
+        assert!(luminosity(inner) > config.stars[0].luminosity(0.));
+    }
+}
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::OrbitConfig,
+    float::{Float, Vec3, consts::PI},
+};
+
+/// Clock of the planet's orbit around the origin or its host star.
+///
+/// The orbit is fixed by its config, so the clock is all the state there is;
+/// everything else follows from it through [`Orbit::state`].
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Orbit {
+    pub time: Float,
+}
+
+/// Where the planet is on its orbit at one instant.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OrbitState {
+    /// Time since the last periapsis passage
+    pub time_since_periapsis: Float,
+    /// Mean anomaly, reduced to `[-π, π)`
+    pub mean_anomaly: Float,
+    pub eccentric_anomaly: Float,
+    /// Residual of Kepler's equation at `eccentric_anomaly`
+    pub kepler_residual: Float,
+    /// Angle between periapsis and the planet as seen from the body it
+    /// orbits
+    pub true_anomaly: Float,
+    /// Distance from the body it orbits
+    pub distance: Float,
+    pub position: Vec3,
+    pub velocity: Vec3,
+}
+
+impl OrbitState {
+    pub fn speed(&self) -> Float {
+        self.velocity.length()
+    }
+}
+
+impl Orbit {
+    pub fn advance(&mut self, dt: Float) {
+        self.time += dt;
+    }
+
+    pub fn state(&self, orbit: &OrbitConfig) -> OrbitState {
+        let OrbitConfig { a, e, .. } = *orbit;
+        let since_periapsis = self.time - orbit.per_time;
+        let mean_anomaly = reduce(orbit.n() * since_periapsis);
+        let kepler = solve_kepler(mean_anomaly, e, orbit.solver, orbit.tolerance);
+        let ecc_anom = kepler.eccentric_anomaly;
+        let (sin, cos) = ecc_anom.sin_cos();
+        let (half_sin, half_cos) = (ecc_anom / 2.).sin_cos();
+        let b = a * (1. - e.powi(2)).sqrt();
+
+        // dE/dt from Kepler's equation M = E - e sin E
+        let ecc_anom_rate = orbit.n() / (1. - e * cos);
+
+        let frame = orbit.frame();
+        OrbitState {
+            time_since_periapsis: since_periapsis.rem_euclid(orbit.period_time),
+            mean_anomaly,
+            eccentric_anomaly: ecc_anom,
+            kepler_residual: kepler.residual,
+            true_anomaly: 2. * ((1. + e).sqrt() * half_sin).atan2((1. - e).sqrt() * half_cos),
+            distance: a * (1. - e * cos),
+            position: frame * Vec3::new(a * (cos - e), b * sin, 0.),
+            velocity: frame * Vec3::new(-a * sin, b * cos, 0.) * ecc_anom_rate,
+        }
+    }
+
+    pub fn position(&self, orbit: &OrbitConfig) -> Vec3 {
+        self.state(orbit).position
+    }
+}
+
+fn reduce(m: Float) -> Float {
+    let reduced_m = m.rem_euclid(2. * PI);
+    if reduced_m < PI {
+        reduced_m
+    } else {
+        reduced_m - 2. * PI
+    }
+}
+
+/// Cap on the iterations of the iterative Kepler solvers
+const KEPLER_ITERATIONS: u32 = 32;
+
+/// How Kepler's equation `E - e sin E = M` is solved for the eccentric
+/// anomaly.
+///
+/// Mikkola's approximation is a cubic starter with one Newton correction and
+/// no convergence check. Newton and Halley start from it and iterate until the
+/// residual is within the orbit's tolerance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeplerSolver {
+    Mikkola,
+    Newton,
+    Halley,
+}
+
+/// An eccentric anomaly and how well it solves Kepler's equation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeplerSolution {
+    pub eccentric_anomaly: Float,
+    /// `E - e sin E - M` at the returned `E`
+    pub residual: Float,
+    /// Iterations beyond the starter
+    pub iterations: u32,
+}
+
+pub fn solve_kepler(m: Float, e: Float, solver: KeplerSolver, tolerance: Float) -> KeplerSolution {
+    let mut ecc_anom = mikkola_approximation(m, e);
+    let mut iterations = 0;
+    loop {
+        let (sin, cos) = ecc_anom.sin_cos();
+        let residual = ecc_anom - e * sin - m;
+        let slope = 1. - e * cos;
+        let step = match solver {
+            KeplerSolver::Mikkola => None,
+            _ if residual.abs() <= tolerance || iterations == KEPLER_ITERATIONS => None,
+            KeplerSolver::Newton => Some(residual / slope),
+            // Halley's method also uses the curvature e sin E
+            KeplerSolver::Halley => {
+                Some(2. * residual * slope / (2. * slope.powi(2) - residual * e * sin))
+            }
+        };
+        match step {
+            Some(step) => {
+                ecc_anom -= step;
+                iterations += 1;
+            }
+            None => {
+                return KeplerSolution {
+                    eccentric_anomaly: ecc_anom,
+                    residual,
+                    iterations,
+                };
+            }
+        }
+    }
+}
+
+/// Mikkola's approximation of the eccentric anomaly for mean anomaly `m` in
+/// `[-π, π)`.
+pub fn mikkola_approximation(m: Float, e: Float) -> Float {
+    let alpha = (1. - e) / (4. * e + 0.5);
+    let beta = 0.5 * m / (4. * e + 0.5);
+
+    let z = (beta + (beta.powi(2) + alpha.powi(3)).sqrt()).powf(1. / 3.);
+
+    let s = z - alpha / z;
+
+    // Approximate eccentric anomaly
+    let mut res = m + e * (3. * s - 4. * s.powi(3));
+
+    // One Newton refinement
+    res -= (res - e * res.sin() - m) / (1. - e * res.cos());
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eccentric() -> OrbitConfig {
+        OrbitConfig {
+            e: 0.6,
+            ..OrbitConfig::default()
+        }
+    }
+
+    /// States around the orbit, periapsis and apoapsis included.
+    fn states(orbit: &OrbitConfig) -> impl Iterator<Item = OrbitState> + '_ {
+        (0..24).map(|i| {
+            let time = orbit.per_time + orbit.period_time * i as Float / 24.;
+            Orbit { time }.state(orbit)
+        })
+    }
+
+    #[test]
+    fn velocity_is_the_rate_of_change_of_position() {
+        let orbit = eccentric();
+        let h = 1e-2;
+        for state in states(&orbit) {
+            let time = orbit.per_time + state.time_since_periapsis;
+            let ahead = Orbit { time: time + h }.position(&orbit);
+            let behind = Orbit { time: time - h }.position(&orbit);
+            let difference = (ahead - behind) / (2. * h);
+            assert!(
+                (difference - state.velocity).length() <= 1e-3 * state.speed(),
+                "{difference} != {} at {time}",
+                state.velocity
+            );
+        }
+    }
+
+    #[test]
+    fn distance_follows_the_conic() {
+        let orbit = eccentric();
+        let OrbitConfig { a, e, .. } = orbit;
+        for state in states(&orbit) {
+            let conic = a * (1. - e * e) / (1. + e * state.true_anomaly.cos());
+            assert!((state.distance - conic).abs() <= 1e-5 * a);
+            assert!((state.position.length() - state.distance).abs() <= 1e-5 * a);
+        }
+    }
+
+    #[test]
+    fn specific_energy_is_conserved() {
+        let orbit = eccentric();
+        // Kepler's third law, μ = n² a³
+        let mu = orbit.n().powi(2) * orbit.a.powi(3);
+        let expected = -mu / (2. * orbit.a);
+        for state in states(&orbit) {
+            let energy = state.speed().powi(2) / 2. - mu / state.distance;
+            assert!(
+                (energy - expected).abs() <= 1e-4 * expected.abs(),
+                "{energy} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn iterative_solvers_meet_the_tolerance() {
+        let tolerance = OrbitConfig::default().tolerance;
+        for solver in [KeplerSolver::Newton, KeplerSolver::Halley] {
+            for e in [0., 0.3, 0.6, 0.9, 0.99] {
+                for i in 0..360 {
+                    let m = reduce(2. * PI * i as Float / 360.);
+                    let solution = solve_kepler(m, e, solver, tolerance);
+                    assert!(
+                        solution.residual.abs() <= tolerance,
+                        "{solver:?} left {} at M = {m}, e = {e}",
+                        solution.residual
+                    );
+                }
+            }
+        }
+    }
+
+    // In single precision the starter loses its accuracy near e = 1
+    #[cfg(not(feature = "f64"))]
+    #[test]
+    fn iteration_corrects_the_starter() {
+        let (m, e) = (reduce(11. * PI / 8.), 0.99);
+        let tolerance = OrbitConfig::default().tolerance;
+        let starter = solve_kepler(m, e, KeplerSolver::Mikkola, tolerance);
+        assert!(starter.residual.abs() > 0.5);
+        for solver in [KeplerSolver::Newton, KeplerSolver::Halley] {
+            let solution = solve_kepler(m, e, solver, tolerance);
+            assert!(solution.iterations > 0);
+            assert!(solution.residual.abs() <= tolerance);
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    config::{AdaptiveConfig, AtmosphereConfig, RadiationConfig},
+    energy_diff::{BlackBody, GrayAtmosphere},
+    float::{Float, widen},
+    grid::Grid,
+    integrator::{Adaptive, Integrator, Method, Rhs, Workspace},
+};
+use ndarray::{Array2, Axis};
+use serde::{Deserialize, Serialize};
+
+// We use a vector because an array of this size would overflow the thread stack.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TempMap(Array2<Float>); // T(x, y)
+impl TempMap {
+    pub fn new(init_temp: Array2<Float>) -> Self {
+        Self(init_temp)
+    }
+
+    /// A field of zeros on `grid`.
+    pub fn zeros(grid: &Grid) -> Self {
+        TempMap::new(Array2::zeros(grid.dim()))
+    }
+
+    pub fn field(&self) -> &Array2<Float> {
+        &self.0
+    }
+
+    pub fn grid(&self) -> Grid {
+        Grid::from_dim(self.0.dim())
+    }
+
+    /// One step of the heat equation `dT/dt = f(T)`, `f` usually being
+    /// [`Diffusion`](crate::diffusion::Diffusion).
+    pub fn apply_heat_eq<R: Rhs + ?Sized>(
+        &mut self,
+        method: Method,
+        f: &mut R,
+        h: Float,
+        work: &mut Workspace,
+    ) {
+        self.pool_polar_caps();
+        method.advance(f, &mut self.0, h, work);
+    }
+
+    /// Integrate the heat equation over `span` in steps sized to keep
+    /// `method`'s error estimate within the tolerance.
+    pub fn apply_heat_eq_adaptive<R: Rhs + ?Sized>(
+        &mut self,
+        adaptive: &mut Adaptive,
+        settings: &AdaptiveConfig,
+        method: Method,
+        f: &mut R,
+        span: Float,
+        work: &mut Workspace,
+    ) {
+        self.pool_polar_caps();
+        adaptive.integrate(settings, &method, f, &mut self.0, span, work);
+    }
+
+    /// Each polar row is a single cap cell for diffusion. Heat deposited
+    /// unevenly on its sub-cells, e.g. by the sun, is spread over the whole
+    /// cap; the sub-cells have equal areas, so this conserves heat.
+    pub fn pool_polar_caps(&mut self) {
+        let Grid { width, height } = self.grid();
+        for y in [0, height - 1] {
+            let mut cap = self.0.column_mut(y);
+            let mean = cap.sum() / width as Float;
+            cap.fill(mean);
+        }
+    }
+
+    pub fn add_heat(&mut self, rhs: &Array2<Float>, scale: Float) {
+        self.0.scaled_add(scale, rhs);
+    }
+
+    /// Set every cell from `f(longitude, colatitude)` at its centre.
+    pub fn set_heat(&mut self, f: fn(Float, Float) -> Float) {
+        let grid = self.grid();
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                self.0[[x, y]] = f(grid.lon(x), grid.colat(y));
+            }
+        }
+    }
+
+    /// Add a reproducible pseudo-random perturbation in `[0, amplitude)` to
+    /// every cell.
+    pub fn perturb(&mut self, seed: u64, amplitude: Float) {
+        // splitmix64, enough for initial conditions and stable across platforms
+        let mut state = seed;
+        for t in self.0.iter_mut() {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            *t += amplitude * (z >> 40) as Float / (1u64 << 24) as Float;
+        }
+    }
+
+    pub fn radiate_black_body(
+        &mut self,
+        method: Method,
+        scale: Float,
+        radiation: &RadiationConfig,
+        work: &mut Workspace,
+    ) {
+        let mut black_body = BlackBody::new(radiation, &self.grid());
+        method.advance(&mut black_body, &mut self.0, scale, work);
+    }
+
+    /// Radiate through a gray atmosphere layer whose temperature is
+    /// `atmosphere`, which changes along with the surface. The two are
+    /// integrated together in `stacked`, the surface above the layer, which
+    /// is kept from call to call like `work`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn radiate_gray_atmosphere(
+        &mut self,
+        atmosphere: &mut TempMap,
+        method: Method,
+        scale: Float,
+        radiation: &RadiationConfig,
+        layer: &AtmosphereConfig,
+        stacked: &mut Array2<Float>,
+        work: &mut Workspace,
+    ) {
+        let mut gray = GrayAtmosphere::new(radiation, layer, &self.grid());
+        let (width, height) = self.0.dim();
+        if stacked.dim() != (2 * width, height) {
+            *stacked = Array2::zeros((2 * width, height));
+        }
+        {
+            let (mut surface, mut layer) = stacked.view_mut().split_at(Axis(0), width);
+            surface.assign(&self.0);
+            layer.assign(&atmosphere.0);
+        }
+        method.advance(&mut gray, stacked, scale, work);
+        let (surface, layer) = stacked.view().split_at(Axis(0), width);
+        self.0.assign(&surface);
+        atmosphere.0.assign(&layer);
+    }
+
+    pub fn get_heat_stats(&self) -> (Float, Float, Float, Float, Float) {
+        let mut max = -Float::INFINITY;
+        let mut min = Float::INFINITY;
+        // Summed in double precision so large grids don't lose the mean
+        let mut avg = 0f64;
+
+        for t in &self.0 {
+            if *t > max {
+                max = *t;
+            }
+            if *t < min {
+                min = *t
+            }
+            avg += widen(*t)
+        }
+
+        let height = self.grid().height;
+        let avg = (avg / self.0.len() as f64) as Float;
+
+        (
+            max,
+            min,
+            avg,
+            self.0[[0, height / 4]],
+            self.0[[0, height * 3 / 4]],
+        )
+    }
+}
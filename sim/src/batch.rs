@@ -0,0 +1,81 @@
+use std::{error::Error, fs, io::Write, path::Path};
+
+use climate_core::{Float, Model, TempMap, checkpoint};
+
+use crate::sampling::TemperatureData;
+
+/// Run the model for `years` more years of its system straight through
+/// `climate_core`, without a window or GPU, and write the sampled
+/// statistics, the final state and a checkpoint to `out_dir`. Every planet
+/// of a system counts the same years, so they all cover the same span.
+pub fn run(mut model: Model, years: f32, out_dir: &Path) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(out_dir)?;
+    let config = model.config();
+    fs::write(out_dir.join("config.toml"), config.to_toml())?;
+
+    let dt = config.planet_dt();
+    let year = config.year();
+    // A year shorter than a tick still reports once per tick
+    let ticks_per_year = ((year / dt).round() as u64).max(1);
+    let ticks = (Float::from(years) * year / dt).round() as u64;
+
+    let mut data = TemperatureData::default();
+
+    for tick in 1..=ticks {
+        model.step(dt);
+        data.push(&model);
+
+        if tick % ticks_per_year == 0 || tick == ticks {
+            let (max, min, avg, _, _) = model.temperature().get_heat_stats();
+            if !(max.is_finite() && min.is_finite()) {
+                return Err(format!("temperature diverged at t = {}", model.time()).into());
+            }
+            eprintln!(
+                "year {:.1}/{years}: max {max}, min {min}, avg {avg}",
+                tick as f32 / ticks_per_year as f32
+            );
+            if let Some(atmosphere) = model.atmosphere_temperature() {
+                let (max, min, avg, _, _) = atmosphere.get_heat_stats();
+                eprintln!("  atmosphere: max {max}, min {min}, avg {avg}");
+            }
+            if let Some(stats) = model.diffusion_stats() {
+                eprintln!("  diffusion steps: {stats}");
+            }
+            if model.config().forcing.is_active() {
+                let (orbit, planet) = model.elements();
+                eprintln!(
+                    "  forcing year {:.0}: e {}, obliquity {}, axis azimuth {}",
+                    model.forcing_years(),
+                    orbit.e,
+                    planet.obliquity,
+                    planet.axis_azimuth
+                );
+            }
+        }
+    }
+
+    data.write_csv(&out_dir.join("stats.csv"))?;
+    data.plot(&out_dir.join("temperature.png").to_string_lossy())?;
+    write_field_csv(&out_dir.join("final_state.csv"), model.temperature())?;
+    if let Some(atmosphere) = model.atmosphere_temperature() {
+        write_field_csv(&out_dir.join("final_atmosphere.csv"), atmosphere)?;
+    }
+    checkpoint::save(&model, out_dir.join("final.ckpt"))?;
+
+    println!("Batch output written to {}", out_dir.display());
+    Ok(())
+}
+
+// One row per latitude from north to south, same layout as the map texture.
+fn write_field_csv(path: &Path, temp: &TempMap) -> Result<(), Box<dyn Error>> {
+    let mut file = fs::File::create(path)?;
+    let grid = temp.grid();
+    for y in 0..grid.height {
+        let row = (0..grid.width)
+            .map(|x| temp.field()[[x, y]].to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        writeln!(file, "{row}")?;
+    }
+    Ok(())
+}
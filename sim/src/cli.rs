@@ -0,0 +1,145 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use clap::{Args, Parser, Subcommand};
+
+/// Real-time climate simulation of a planet on an elliptical orbit.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Open a window and run the simulation interactively (the default)
+    Run {
+        #[command(flatten)]
+        sim: SimArgs,
+        /// Exit after this many seconds of simulated time
+        #[arg(long, value_name = "SECONDS", value_parser = positive)]
+        duration: Option<f32>,
+    },
+    /// Render the planet view offscreen into an MP4 file
+    Record {
+        #[command(flatten)]
+        sim: SimArgs,
+        /// Video file to write
+        #[arg(short, long, default_value = "recording.mp4")]
+        output: PathBuf,
+        /// Video resolution
+        #[arg(
+            long,
+            default_value = "3840x2160",
+            value_name = "WIDTHxHEIGHT",
+            value_parser = video_resolution
+        )]
+        resolution: Resolution,
+        /// Stop recording after this many seconds of simulated time
+        #[arg(long, value_name = "SECONDS", value_parser = positive)]
+        duration: Option<f32>,
+        /// Also sample temperatures and plot them to `temperature.png` at the end
+        #[arg(long)]
+        sample: bool,
+    },
+    /// Run interactively, sampling temperatures and plotting them on exit
+    Sample {
+        #[command(flatten)]
+        sim: SimArgs,
+        /// Plot to write
+        #[arg(short, long, default_value = "temperature.png")]
+        output: PathBuf,
+        /// Plot and exit after this many seconds [default: simulation.max_time]
+        #[arg(long, value_name = "SECONDS", value_parser = positive)]
+        duration: Option<f32>,
+    },
+    /// Run headless, without a window or GPU, for a number of years
+    Batch {
+        #[command(flatten)]
+        sim: SimArgs,
+        /// Directory for the statistics, plot, final state and checkpoint
+        #[arg(short, long, default_value = "batch_output")]
+        output: PathBuf,
+        /// Number of years to simulate, in orbits of the planet or of the
+        /// top-level orbit of a system of several
+        #[arg(long, default_value_t = 1., value_parser = positive)]
+        years: f32,
+    },
+    /// Print the resolved configuration and derived quantities, or those of
+    /// the checkpoints given with --restart
+    Inspect {
+        #[command(flatten)]
+        sim: SimArgs,
+    },
+}
+
+#[derive(Args, Default)]
+pub struct SimArgs {
+    /// TOML config file; anything it leaves out keeps its default
+    #[arg(short, long, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+    /// Seed for a random perturbation of the initial temperatures
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Grid resolution in cells, overriding the config
+    #[arg(long, value_name = "WIDTHxHEIGHT")]
+    pub grid: Option<Resolution>,
+    /// Resume from a checkpoint, including the config it was saved with; a
+    /// system of several planets needs the checkpoint of every planet
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 1..,
+        conflicts_with_all = ["config", "seed", "grid"]
+    )]
+    pub restart: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s
+            .split_once('x')
+            .ok_or_else(|| format!("expected WIDTHxHEIGHT, got `{s}`"))?;
+        let parse = |v: &str| match v.parse::<u32>() {
+            Ok(v) if v > 0 => Ok(v),
+            _ => Err(format!("`{v}` is not a positive integer")),
+        };
+        Ok(Resolution {
+            width: parse(width)?,
+            height: parse(height)?,
+        })
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+fn positive(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(v) if v > 0. && v.is_finite() => Ok(v),
+        _ => Err(format!("`{s}` is not a positive number")),
+    }
+}
+
+// The encoder takes 16-bit frame sizes.
+fn video_resolution(s: &str) -> Result<Resolution, String> {
+    let resolution: Resolution = s.parse()?;
+    let max = u32::from(u16::MAX);
+    if resolution.width > max || resolution.height > max {
+        return Err(format!(
+            "`{s}` is larger than the largest video, {max}x{max}"
+        ));
+    }
+    Ok(resolution)
+}
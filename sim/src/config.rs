@@ -0,0 +1,6 @@
+use bevy::prelude::*;
+use climate_core::SimConfig;
+
+/// The run's configuration, readable by every system.
+#[derive(Resource, Deref)]
+pub struct Config(pub SimConfig);
@@ -1,4 +1,4 @@
-use std::{env, f32::consts::PI, fs::File};
+use std::{fs::File, path::PathBuf, process};
 
 use bevy::{
     app::{RunMode, ScheduleRunnerPlugin},
@@ -7,33 +7,315 @@ use bevy::{
     winit::WinitPlugin,
 };
 use bevy_capture::{Capture, CapturePlugin, encoder::mp4_openh264::Mp4Openh264Encoder};
+use clap::Parser;
+use climate_core::{
+    Float, Grid, Model, SimConfig, checkpoint,
+    float::{narrow, narrow_quat, narrow_vec3},
+};
 
+mod batch;
+mod cli;
+mod config;
 mod consts;
 mod planet;
 use crate::{
-    consts::UNIVERSAL_UPDATE_RATE,
-    planet::{Planet, PlanetRenderTexture, PlanetStats},
-    view::SimulationSpecs,
+    cli::{Cli, Command, SimArgs},
+    config::Config,
+    planet::{
+        FocusedPlanet, InitialModels, Moon, Planet, PlanetRenderTexture, PlanetStats, ShownLayer,
+        Star,
+    },
+    view::{Recording, SimulationSpecs},
 };
-mod energy_diff;
-mod rk4;
 mod temp;
 mod view;
 
 mod sampling;
 
 fn main() {
-    let mut record = false;
-    let mut sample = false;
-    let args = env::args();
-    for arg in args {
-        match &arg[..] {
-            "record" => record = true,
-            "sample" => sample = true,
-            _ => {}
+    let cli = Cli::parse();
+    let command = cli.command.unwrap_or(Command::Run {
+        sim: SimArgs::default(),
+        duration: None,
+    });
+
+    match command {
+        Command::Run { sim, duration } => {
+            run_app(
+                load_planets(&sim),
+                duration,
+                SimulationSpecs {
+                    record: None,
+                    plot: None,
+                },
+                duration.is_some(),
+            );
+        }
+        Command::Record {
+            sim,
+            output,
+            resolution,
+            duration,
+            sample,
+        } => {
+            run_app(
+                load_planets(&sim),
+                duration,
+                SimulationSpecs {
+                    record: Some(Recording { output, resolution }),
+                    plot: sample.then(|| PathBuf::from("temperature.png")),
+                },
+                duration.is_some() && !sample,
+            );
         }
+        Command::Sample {
+            sim,
+            output,
+            duration,
+        } => {
+            run_app(
+                load_planets(&sim),
+                duration,
+                SimulationSpecs {
+                    record: None,
+                    plot: Some(output),
+                },
+                false,
+            );
+        }
+        Command::Batch { sim, output, years } => {
+            // Headless: no Bevy app at all, just the core model. Several
+            // planets each get a directory of their own.
+            let planets = load_planets(&sim);
+            let several = planets.len() > 1;
+            for (name, model) in planets {
+                let output = if several {
+                    output.join(&name)
+                } else {
+                    output.clone()
+                };
+                if let Err(err) = batch::run(model, years, &output) {
+                    eprintln!("batch run of {name} failed: {err}");
+                    process::exit(1);
+                }
+            }
+        }
+        Command::Inspect { sim } => {
+            let planets = load_planets(&sim);
+            let several = planets.len() > 1;
+            for (name, model) in &planets {
+                if several {
+                    println!("# Planet {name}");
+                }
+                inspect(model);
+                if several {
+                    println!();
+                }
+            }
+        }
+    }
+}
+
+/// Every planet to run by name, from a checkpoint or with a model of its
+/// own for each planet of the config.
+fn load_planets(sim: &SimArgs) -> Vec<(String, Model)> {
+    if !sim.restart.is_empty() {
+        return restore_planets(&sim.restart).unwrap_or_else(|err| {
+            eprintln!("{err}");
+            process::exit(1);
+        });
+    }
+
+    let mut config = match &sim.config {
+        Some(path) => SimConfig::load(path).unwrap_or_else(|err| {
+            eprintln!("{}: {err}", path.display());
+            process::exit(1);
+        }),
+        None => SimConfig::default(),
+    };
+    if let Some(seed) = sim.seed {
+        config.initial.seed = Some(seed);
+    }
+    if let Some(grid) = sim.grid {
+        config.grid = Grid {
+            width: grid.width as usize,
+            height: grid.height as usize,
+        };
+    }
+    if let Err(err) = config.validate() {
+        eprintln!("{err}");
+        process::exit(2);
+    }
+    config
+        .planet_configs()
+        .into_iter()
+        .map(|(name, config)| (name, Model::new(config)))
+        .collect()
+}
+
+/// Every planet of a run from one checkpoint each, in the order of their
+/// system. A system only resumes with all of its planets at the same time.
+fn restore_planets(paths: &[PathBuf]) -> Result<Vec<(String, Model)>, String> {
+    let mut planets = Vec::with_capacity(paths.len());
+    for path in paths {
+        let model = checkpoint::load(path).map_err(|err| format!("{}: {err}", path.display()))?;
+        planets.push((path, model));
     }
 
+    let (first_path, first) = &planets[0];
+    let Some(system) = first.config().system.clone() else {
+        if planets.len() > 1 {
+            return Err(format!(
+                "{} holds a single planet, not one of a system; restart it on its own",
+                first_path.display()
+            ));
+        }
+        let (_, model) = planets.pop().expect("there is one checkpoint");
+        return Ok(vec![(model.config().name().to_string(), model)]);
+    };
+    for (path, model) in &planets {
+        let config = model.config();
+        let Some(other) = &config.system else {
+            return Err(format!(
+                "{} holds a single planet, not one of a system",
+                path.display()
+            ));
+        };
+        if other.planets != system.planets || other.year != system.year {
+            return Err(format!(
+                "{} is a planet of another system than {}",
+                path.display(),
+                first_path.display()
+            ));
+        }
+        if model.time() != first.time() {
+            return Err(format!(
+                "{} was saved at t = {} but {} at t = {}",
+                path.display(),
+                model.time(),
+                first_path.display(),
+                first.time()
+            ));
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(system.planets.len());
+    let mut missing = Vec::new();
+    for name in &system.planets {
+        let mut found = planets
+            .iter()
+            .filter(|(_, model)| model.config().name() == name);
+        match (found.next(), found.next()) {
+            (Some((_, model)), None) => ordered.push((name.clone(), model.clone())),
+            (Some(_), Some(_)) => return Err(format!("planet `{name}` is given twice")),
+            (None, _) => missing.push(name.as_str()),
+        }
+    }
+    if !missing.is_empty() {
+        return Err(format!(
+            "the checkpoints of {} are missing; a system resumes from one checkpoint per planet",
+            missing.join(", ")
+        ));
+    }
+    Ok(ordered)
+}
+
+fn inspect(model: &Model) {
+    let config = model.config();
+    print!("{}", config.to_toml());
+    println!();
+    println!("# State");
+    println!("# time = {}", model.time());
+    let orbit = model.orbit_state();
+    println!("# position = {}", orbit.position);
+    println!("# velocity = {}", orbit.velocity);
+    println!("# distance = {}", orbit.distance);
+    println!(
+        "# anomalies = mean {}, eccentric {}, true {} rad",
+        orbit.mean_anomaly, orbit.eccentric_anomaly, orbit.true_anomaly
+    );
+    println!("# time since periapsis = {}", orbit.time_since_periapsis);
+    println!("# kepler residual = {:e}", orbit.kepler_residual);
+    println!("# irradiance = {}", model.irradiance());
+    let years = model.forcing_years();
+    for (i, (star, source)) in config.stars.iter().zip(model.sources()).enumerate() {
+        println!(
+            "# star {i}: luminosity = {}, position = {}, irradiance = {}",
+            star.luminosity(years),
+            source.position,
+            source.irradiance
+        );
+    }
+    if config.forcing.is_active() {
+        let (orbit, planet) = model.elements();
+        println!("# forcing year = {}", model.forcing_years());
+        println!(
+            "# forced elements = e {}, obliquity {}, axis azimuth {}",
+            orbit.e, planet.obliquity, planet.axis_azimuth
+        );
+    }
+    println!("# rotation = {}", model.rotation());
+    if let Some(atmosphere) = model.atmosphere_temperature() {
+        let (max, min, avg, _, _) = atmosphere.get_heat_stats();
+        println!("# atmosphere temperature = max {max}, min {min}, avg {avg}");
+    }
+    for i in 0..config.moons.len() {
+        let position = model.moon_position(i);
+        let irradiance: Float = model
+            .sources_at(position)
+            .iter()
+            .map(|source| source.irradiance)
+            .sum();
+        println!(
+            "# moon {i}: position = {position}, distance = {}, irradiance = {irradiance}",
+            position.distance(model.position())
+        );
+    }
+    println!();
+    println!("# Derived");
+    println!("# cells = {}", config.grid.len());
+    println!("# mean motion = {} rad/s", config.orbit.n());
+    println!(
+        "# solar day = {} s",
+        config.planet.solar_day(config.orbit.period_time)
+    );
+    println!(
+        "# ticks per orbit = {}",
+        config.orbit.period_time / config.planet_dt()
+    );
+    if let Some(adaptive) = &config.diffusion.adaptive {
+        println!(
+            "# diffusion dt = adaptive (tolerance {})",
+            adaptive.tolerance
+        );
+    } else {
+        let steps = config.diffusion_steps();
+        println!(
+            "# diffusion dt = {} ({steps} steps per tick)",
+            config.planet_dt() / steps as Float
+        );
+    }
+    if let Some(stats) = model.diffusion_stats() {
+        println!("# diffusion steps = {stats}");
+    }
+}
+
+fn run_app(
+    planets: Vec<(String, Model)>,
+    duration: Option<f32>,
+    specs: SimulationSpecs,
+    exit_at_max_time: bool,
+) {
+    // The models keep the config they were created with, which differ only
+    // in their planets; the frontend only overrides how long to run for.
+    let mut config = planets[0].1.config().clone();
+    if let Some(duration) = duration {
+        config.simulation.max_time = duration;
+    }
+
+    let record = specs.record.is_some();
+    let sample = specs.plot.is_some();
+
     let mut app = App::new();
 
     if record {
@@ -57,16 +339,21 @@ fn main() {
     }
 
     if sample {
-        app.add_systems(Startup, sampling::start_sampling)
+        app.add_systems(Startup, sampling::start_sampling.after(setup_system))
             .add_systems(Update, sampling::plot_data)
             .add_systems(FixedUpdate, sampling::sample_temp);
     }
 
+    if exit_at_max_time {
+        app.add_systems(Update, exit_after_max_time);
+    }
+
     app.add_systems(
         Startup,
         (
-            move |commands: Commands| setup_sim_specs(commands, record),
-            view::setup_texture,
+            move |mut commands: Commands| {
+                commands.spawn(specs.clone());
+            },
             setup_system,
             view::setup_cameras,
         )
@@ -76,98 +363,153 @@ fn main() {
         Update,
         (
             temp::apply_temp_image,
+            temp::apply_moon_images,
             planet::update_stats,
             view::toggle_view,
             view::update_camera,
+            view::update_map,
+            planet::cycle_focus,
+            planet::toggle_layer,
             view::physics_control,
+            planet::save_checkpoint,
         ),
     )
-    // Systems on fixed clock! Only update on UNIVERSAL_UPDATE_RATE.
+    // Systems on fixed clock! Only update on the configured update rate.
     .add_systems(
         FixedUpdate,
-        (
-            planet::move_planet,
-            planet::rotate,
-            temp::apply_heat_eq,
-            energy_diff::apply_heat_in,
-            energy_diff::apply_black_body_radiation,
-        ),
+        (planet::step_model, planet::move_stars, planet::move_moons).chain(),
     )
-    .insert_resource(Time::<Fixed>::from_seconds(UNIVERSAL_UPDATE_RATE));
+    .insert_resource(Time::<Fixed>::from_seconds(config.simulation.update_rate))
+    .insert_resource(Config(config))
+    .insert_resource(InitialModels(planets))
+    .init_resource::<ShownLayer>();
 
     app.run();
 }
 
-fn setup_sim_specs(mut commands: Commands, record: bool) {
-    commands.spawn(SimulationSpecs { record });
+fn exit_after_max_time(
+    time: Res<Time<Virtual>>,
+    config: Res<Config>,
+    mut exit_events: ResMut<Events<AppExit>>,
+) {
+    if time.elapsed_secs() > config.simulation.max_time {
+        exit_events.send(AppExit::Success);
+    }
 }
 
-fn capture_frame(mut capture: Query<&mut Capture>) {
+fn capture_frame(mut capture: Query<&mut Capture>, sim_specs_query: Query<&SimulationSpecs>) {
     let mut capture = capture.single_mut().unwrap();
+    let Recording { output, resolution } =
+        sim_specs_query.single().unwrap().record.as_ref().unwrap();
     if !capture.is_capturing() {
+        // Both fit, the resolution is checked when the arguments are parsed
         capture.start(
-            Mp4Openh264Encoder::new(File::create("recording.mp4").unwrap(), 3840, 2160).unwrap(),
+            Mp4Openh264Encoder::new(
+                File::create(output).unwrap(),
+                resolution.width as u16,
+                resolution.height as u16,
+            )
+            .unwrap(),
         );
     }
 }
 
-#[derive(Component)]
-struct Star;
-
 fn setup_system(
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    render_tex: Res<PlanetRenderTexture>,
+    mut images: ResMut<Assets<Image>>,
+    initial_models: Res<InitialModels>,
 ) {
-    let material_handle = materials.add(StandardMaterial {
-        base_color_texture: Some(render_tex.0.clone()),
-        alpha_mode: AlphaMode::Blend,
-        unlit: false,
+    let star_mesh = meshes.add(Sphere::default().mesh().uv(40, 20));
+
+    // The stars are the same for every planet
+    let (_, first) = &initial_models.0[0];
+    let stars = &first.config().stars;
+    let star_positions = first.star_positions();
+    let total_luminosity: Float = stars.iter().map(|star| star.luminosity).sum();
+    let star_material = materials.add(StandardMaterial {
+        base_color: Color::linear_rgb(1., 0.98, 0.20),
+        unlit: true,
         ..default()
     });
+    for (i, (star, position)) in stars.iter().zip(star_positions).enumerate() {
+        let translation = narrow_vec3(position);
+        commands.spawn((
+            Mesh3d(star_mesh.clone()),
+            MeshMaterial3d(star_material.clone()),
+            Transform::from_translation(translation),
+            Star(i),
+        ));
+        commands.spawn((
+            PointLight {
+                shadows_enabled: true,
+                intensity: 10_000_000. * narrow(star.luminosity / total_luminosity),
+                range: 100.0,
+                shadow_depth_bias: 0.2,
+                ..default()
+            },
+            Transform::from_translation(translation),
+            Star(i),
+        ));
+    }
 
-    let planet_mesh = meshes.add(Sphere::default().mesh().uv(32, 18));
-    let star_mesh = meshes.add(Sphere::default().mesh().uv(40, 20));
-
-    let mut transform = Transform::from_xyz(3., 0., 0.);
-    transform.rotate_axis(Dir3::Y, 23. * PI / 180.);
-    commands.spawn((
-        Mesh3d(planet_mesh),
-        MeshMaterial3d(material_handle),
-        transform,
-        Planet::default(),
-    ));
-    commands.spawn((
-        Mesh3d(star_mesh),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::linear_rgb(1., 0.98, 0.20),
-            unlit: true,
+    let mut focus = None;
+    for (n, (name, model)) in initial_models.0.iter().enumerate() {
+        let config = model.config();
+        let render_tex = images.add(view::heat_image(&config.grid));
+        let material_handle = materials.add(StandardMaterial {
+            base_color_texture: Some(render_tex.clone()),
+            alpha_mode: AlphaMode::Blend,
+            unlit: false,
             ..default()
-        })),
-        Transform::from_xyz(0., 0., 0.),
-        Star,
-    ));
-    commands.spawn((
-        PointLight {
-            shadows_enabled: true,
-            intensity: 10_000_000.,
-            range: 100.0,
-            shadow_depth_bias: 0.2,
-            ..default()
-        },
-        Transform::from_xyz(0., 0., 0.),
-    ));
-    commands.spawn((
-        Text::new(""),
-        Node {
-            position_type: PositionType::Absolute,
-            top: Val::Px(12.),
-            left: Val::Px(12.),
-            ..default()
-        },
-        PlanetStats,
-    ));
+        });
+        let planet_mesh = meshes.add(Sphere::new(narrow(config.planet.radius)).mesh().uv(32, 18));
+        let planet = commands
+            .spawn((
+                Mesh3d(planet_mesh),
+                MeshMaterial3d(material_handle),
+                Transform::from_translation(narrow_vec3(model.position()))
+                    .with_rotation(narrow_quat(model.rotation())),
+                Planet(model.clone()),
+                Name::new(name.clone()),
+                PlanetRenderTexture(render_tex),
+            ))
+            .id();
+        focus.get_or_insert(planet);
+
+        for (i, moon) in config.moons.iter().enumerate() {
+            let texture = images.add(view::heat_image(&config.grid));
+            commands.spawn((
+                Mesh3d(meshes.add(Sphere::new(narrow(moon.body.radius)).mesh().uv(32, 18))),
+                MeshMaterial3d(materials.add(StandardMaterial {
+                    base_color_texture: Some(texture.clone()),
+                    ..default()
+                })),
+                Transform::from_translation(narrow_vec3(model.moon_position(i)))
+                    .with_rotation(narrow_quat(model.moon_rotation(i))),
+                Moon {
+                    planet,
+                    index: i,
+                    texture,
+                },
+            ));
+        }
+
+        // The stats of the planets side by side
+        commands.spawn((
+            Text::new(""),
+            Node {
+                position_type: PositionType::Absolute,
+                top: Val::Px(12.),
+                left: Val::Px(12. + 280. * n as f32),
+                ..default()
+            },
+            PlanetStats(planet),
+        ));
+    }
+    commands.insert_resource(FocusedPlanet(focus.expect("there is always a planet")));
+    commands.remove_resource::<InitialModels>();
 }
 
 // // Debug colors
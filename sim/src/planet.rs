@@ -1,85 +1,173 @@
-use std::f32::consts::PI;
+use std::path::{Path, PathBuf};
 
 use bevy::prelude::*;
+use climate_core::{
+    Model, checkpoint,
+    float::{narrow_quat, narrow_vec3},
+};
 
-use crate::consts::*;
+use crate::config::Config;
 
-#[derive(Resource)]
+/// The texture a planet's temperature is drawn into.
+#[derive(Component)]
 pub struct PlanetRenderTexture(pub Handle<Image>);
 
-#[derive(Component)]
-pub struct Planet {
-    pub time: f32,
-    pub vx: f32,
-    pub vy: f32,
-}
+/// A planet in the scene, driven by its own climate model.
+#[derive(Component, Default)]
+pub struct Planet(pub Model);
 
-impl Default for Planet {
-    fn default() -> Self {
-        Planet {
-            time: 0.,
-            vx: 0.,
-            vy: 2.,
-        }
-    }
+/// The planets to spawn by name, either fresh or from a checkpoint.
+#[derive(Resource)]
+pub struct InitialModels(pub Vec<(String, Model)>);
+
+/// Which temperature field the planets are drawn with.
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ShownLayer {
+    #[default]
+    Surface,
+    Atmosphere,
 }
 
-pub fn rotate(mut query: Query<&mut Transform, With<Planet>>, time: Res<Time>) {
-    for mut transform in &mut query {
-        let forward = transform.forward();
-        transform.rotate_axis(forward, time.delta_secs() / 2.);
+/// Switch between drawing the surface and the atmosphere layer.
+pub fn toggle_layer(keyboard: Res<ButtonInput<KeyCode>>, mut layer: ResMut<ShownLayer>) {
+    if keyboard.just_pressed(KeyCode::KeyL) {
+        *layer = match *layer {
+            ShownLayer::Surface => ShownLayer::Atmosphere,
+            ShownLayer::Atmosphere => ShownLayer::Surface,
+        };
     }
 }
 
-pub fn move_planet(mut planet_query: Query<(&mut Transform, &mut Planet)>) {
-    let (mut transform, mut planet) = planet_query.single_mut().unwrap();
-    planet.time += PLANET_DT;
+/// The planet the camera follows and the map shows.
+#[derive(Resource)]
+pub struct FocusedPlanet(pub Entity);
 
-    let ecc_anom = mikkola_approximation(planet.time);
-    transform.translation.x = A * (ecc_anom.cos() - E);
-    transform.translation.y = A * (1. - E.powi(2)).sqrt() * ecc_anom.sin();
-}
+pub fn step_model(mut planet_query: Query<(&mut Transform, &mut Planet)>, config: Res<Config>) {
+    for (mut transform, mut planet) in &mut planet_query {
+        planet.0.step(config.planet_dt());
 
-fn reduce(m: f32) -> f32 {
-    let reduced_m = m % (2. * PI);
-    if reduced_m < PI {
-        reduced_m
-    } else {
-        reduced_m - 2. * PI
+        transform.translation = narrow_vec3(planet.0.position());
+        transform.rotation = narrow_quat(planet.0.rotation());
     }
 }
 
-fn mikkola_approximation(t: f32) -> f32 {
-    let m = reduce(N * (t - PER_TIME));
+/// A star in the scene, or its light, by its index in the config.
+#[derive(Component)]
+pub struct Star(pub usize);
+
+// Every planet shares the stars and the clock, so any of them will do.
+pub fn move_stars(planet_query: Query<&Planet>, mut star_query: Query<(&mut Transform, &Star)>) {
+    let Some(planet) = planet_query.iter().next() else {
+        return;
+    };
+    let positions = planet.0.star_positions();
+    for (mut transform, star) in &mut star_query {
+        transform.translation = narrow_vec3(positions[star.0]);
+    }
+}
 
-    let alpha = (1. - E) / (4. * E + 0.5);
-    let beta = 0.5 * m / (4. * E + 0.5);
+/// A moon of `planet` by its index in the config, with the texture its
+/// temperature is drawn into.
+#[derive(Component)]
+pub struct Moon {
+    pub planet: Entity,
+    pub index: usize,
+    pub texture: Handle<Image>,
+}
 
-    let z = (beta + (beta.powi(2) + alpha.powi(3)).sqrt()).powf(1. / 3.);
+pub fn move_moons(planet_query: Query<&Planet>, mut moon_query: Query<(&mut Transform, &Moon)>) {
+    for (mut transform, moon) in &mut moon_query {
+        let Ok(planet) = planet_query.get(moon.planet) else {
+            continue;
+        };
+        transform.translation = narrow_vec3(planet.0.moon_position(moon.index));
+        transform.rotation = narrow_quat(planet.0.moon_rotation(moon.index));
+    }
+}
 
-    let s = z - alpha / z;
+/// Move the focus on to the next planet.
+pub fn cycle_focus(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut focus: ResMut<FocusedPlanet>,
+    planet_query: Query<Entity, With<Planet>>,
+) {
+    if keyboard.just_pressed(KeyCode::Tab) {
+        let mut planets: Vec<Entity> = planet_query.iter().collect();
+        planets.sort();
+        let current = planets.iter().position(|&planet| planet == focus.0);
+        let next = current.map_or(0, |i| (i + 1) % planets.len());
+        focus.0 = planets[next];
+    }
+}
 
-    // Approximate eccentric anomaly
-    let mut res = m + E * (3. * s - 4. * s.powi(3));
+pub fn save_checkpoint(keyboard: Res<ButtonInput<KeyCode>>, planet_query: Query<(&Planet, &Name)>) {
+    if keyboard.just_pressed(KeyCode::KeyK) {
+        let several = planet_query.iter().len() > 1;
+        for (planet, name) in &planet_query {
+            let path = Path::new("checkpoint.ckpt");
+            let path = if several {
+                per_planet(path, name)
+            } else {
+                path.to_path_buf()
+            };
+            match checkpoint::save(&planet.0, &path) {
+                Ok(()) => info!("Saved {} at t = {}", path.display(), planet.0.time()),
+                Err(err) => error!("Could not save {}: {err}", path.display()),
+            }
+        }
+    }
+}
 
-    // One Newton refinement
-    res -= (res - E * res.sin() - m) / (1. - E * res.sin());
-    res
+/// `path` with `name` added to the file name, for the output of one of
+/// several planets.
+pub fn per_planet(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let file = match path.extension() {
+        Some(extension) => format!("{stem}_{name}.{}", extension.to_string_lossy()),
+        None => format!("{stem}_{name}"),
+    };
+    path.with_file_name(file)
 }
 
+/// The HUD text of a planet.
 #[derive(Component)]
-pub struct PlanetStats;
+pub struct PlanetStats(pub Entity);
 
 pub fn update_stats(
-    planet_query: Query<(&Transform, &Planet)>,
-    mut text_query: Query<&mut Text, With<PlanetStats>>,
+    planet_query: Query<(&Planet, &Name)>,
+    mut text_query: Query<(&mut Text, &PlanetStats)>,
 ) {
-    let (transform, planet) = planet_query.single().unwrap();
-    let mut text = text_query.single_mut().unwrap();
-
-    let r = (transform.translation.x.powi(2) + transform.translation.y.powi(2)).sqrt();
-    let angle = transform.translation.y.atan2(transform.translation.x);
-    let speed = (planet.vx.powi(2) + planet.vy.powi(2)).sqrt();
-
-    text.0 = format!("Planet Stats:\nDistance: {r}\nAngle: {angle}\nSpeed: {speed}");
+    for (mut text, stats) in &mut text_query {
+        let Ok((planet, name)) = planet_query.get(stats.0) else {
+            continue;
+        };
+
+        let orbit = planet.0.orbit_state();
+        text.0 = format!(
+            "{name} Stats:\nDistance: {:.3}\nTrue anomaly: {:.1}°\nSpeed: {:.3}\nIrradiance: {:.4}",
+            orbit.distance,
+            orbit.true_anomaly.to_degrees(),
+            orbit.speed(),
+            planet.0.irradiance()
+        );
+        if let Some(atmosphere) = planet.0.atmosphere_temperature() {
+            let (_, _, avg, _, _) = atmosphere.get_heat_stats();
+            text.0 += &format!("\nAtmosphere temperature: {avg:.2}");
+        }
+        if planet.0.config().forcing.is_active() {
+            let (orbit, spin) = planet.0.elements();
+            text.0 += &format!(
+                "\nForcing year: {:.0}\nEccentricity: {:.4}\nObliquity: {:.2}°",
+                planet.0.forcing_years(),
+                orbit.e,
+                spin.obliquity
+            );
+        }
+        if let Some(stats) = planet.0.diffusion_stats() {
+            text.0 += &format!(
+                "\nDiffusion steps: {} ({} rejected)\nStep size: {:.3e}",
+                stats.accepted, stats.rejected, stats.last_step
+            );
+        }
+    }
 }
@@ -1,4 +1,4 @@
-use std::error::Error;
+use std::{error::Error, fs::File, io::Write, path::Path};
 
 use bevy::app::AppExit;
 use bevy::prelude::*;
@@ -8,85 +8,168 @@ use plotters::{
     style::{BLACK, Color, FontStyle, IntoFont, IntoTextStyle, RGBColor, WHITE},
 };
 
-use crate::{consts::MAX_TIME, temp::TempMap};
+use climate_core::{Float, Model};
 
-#[derive(Component)]
+use crate::{
+    config::Config,
+    planet::{Planet, per_planet},
+    view::SimulationSpecs,
+};
+
+/// The sampled history of the planet it is attached to.
+#[derive(Component, Default)]
 pub struct TemperatureData {
-    southern_sample_temp: Vec<f32>,
-    northern_sample_temp: Vec<f32>,
-    max_temp: Vec<f32>,
-    min_temp: Vec<f32>,
-    avg_temp: Vec<f32>,
-    time: Vec<f32>,
+    southern_sample_temp: Vec<Float>,
+    northern_sample_temp: Vec<Float>,
+    max_temp: Vec<Float>,
+    min_temp: Vec<Float>,
+    avg_temp: Vec<Float>,
+    time: Vec<Float>,
+    distance: Vec<Float>,
+    true_anomaly: Vec<Float>,
+    eccentricity: Vec<Float>,
+    obliquity: Vec<Float>,
+    axis_azimuth: Vec<Float>,
+    /// Sampled only when the model has an atmosphere layer
+    atmosphere_max: Vec<Float>,
+    atmosphere_min: Vec<Float>,
+    atmosphere_avg: Vec<Float>,
 }
 
-pub fn start_sampling(mut commands: Commands) {
-    commands.spawn(TemperatureData {
-        southern_sample_temp: vec![],
-        northern_sample_temp: vec![],
-        max_temp: vec![],
-        min_temp: vec![],
-        avg_temp: vec![],
-        time: vec![],
-    });
-}
+impl TemperatureData {
+    pub fn push(&mut self, model: &Model) {
+        let t = model.time();
+        let orbit = model.orbit_state();
+        self.time.push(t);
+        self.distance.push(orbit.distance);
+        self.true_anomaly.push(orbit.true_anomaly);
+        let (elements, planet) = model.elements();
+        self.eccentricity.push(elements.e);
+        self.obliquity.push(planet.obliquity);
+        self.axis_azimuth.push(planet.axis_azimuth);
+        let (max, min, avg, southern_sample, northern_sample) =
+            model.temperature().get_heat_stats();
 
-pub fn sample_temp(
-    temp_query: Query<&TempMap>,
-    mut data_query: Query<&mut TemperatureData>,
-    time: Res<Time<Fixed>>,
-) {
-    let temp = temp_query.single().unwrap();
-    let mut data = data_query.single_mut().unwrap();
+        // info!("time: {t}, max: {max}, min: {min}, avg: {avg}");
 
-    let t = time.elapsed_secs();
-    data.time.push(t);
-    let (max, min, avg, southern_sample, northern_sample) = temp.get_heat_stats();
+        self.southern_sample_temp.push(southern_sample);
+        self.northern_sample_temp.push(northern_sample);
+        self.max_temp.push(max);
+        self.min_temp.push(min);
+        self.avg_temp.push(avg);
 
-    // info!("time: {t}, max: {max}, min: {min}, avg: {avg}");
+        if let Some(atmosphere) = model.atmosphere_temperature() {
+            let (max, min, avg, _, _) = atmosphere.get_heat_stats();
+            self.atmosphere_max.push(max);
+            self.atmosphere_min.push(min);
+            self.atmosphere_avg.push(avg);
+        }
+    }
+
+    pub fn write_csv(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut file = File::create(path)?;
+        let atmosphere = !self.atmosphere_avg.is_empty();
+        write!(
+            file,
+            "time,distance,true_anomaly,eccentricity,obliquity,axis_azimuth,southern,northern,max,min,avg"
+        )?;
+        if atmosphere {
+            write!(file, ",atmosphere_max,atmosphere_min,atmosphere_avg")?;
+        }
+        writeln!(file)?;
+        for i in 0..self.time.len() {
+            write!(
+                file,
+                "{},{},{},{},{},{},{},{},{},{},{}",
+                self.time[i],
+                self.distance[i],
+                self.true_anomaly[i],
+                self.eccentricity[i],
+                self.obliquity[i],
+                self.axis_azimuth[i],
+                self.southern_sample_temp[i],
+                self.northern_sample_temp[i],
+                self.max_temp[i],
+                self.min_temp[i],
+                self.avg_temp[i]
+            )?;
+            if atmosphere {
+                write!(
+                    file,
+                    ",{},{},{}",
+                    self.atmosphere_max[i], self.atmosphere_min[i], self.atmosphere_avg[i]
+                )?;
+            }
+            writeln!(file)?;
+        }
+        Ok(())
+    }
 
-    data.southern_sample_temp.push(southern_sample);
-    data.northern_sample_temp.push(northern_sample);
-    data.max_temp.push(max);
-    data.min_temp.push(min);
-    data.avg_temp.push(avg);
+    pub fn plot(&self, out_name: &str) -> Result<(), Box<dyn Error>> {
+        plot_data_temperature(
+            &self.time,
+            &self.southern_sample_temp,
+            &self.northern_sample_temp,
+            &self.max_temp,
+            &self.min_temp,
+            &self.avg_temp,
+            &self.atmosphere_avg,
+            "Time [Ti.U.]",
+            "Temperature [Te.U.]",
+            "Temperature with regards to time",
+            out_name,
+        )
+    }
+}
+
+pub fn start_sampling(mut commands: Commands, planet_query: Query<Entity, With<Planet>>) {
+    for planet in &planet_query {
+        commands.entity(planet).insert(TemperatureData::default());
+    }
+}
+
+pub fn sample_temp(mut planet_query: Query<(&Planet, &mut TemperatureData)>) {
+    for (planet, mut data) in &mut planet_query {
+        data.push(&planet.0);
+    }
 }
 
 pub fn plot_data(
-    data_query: Query<&TemperatureData>,
+    data_query: Query<(&TemperatureData, &Name)>,
     keyboard: Res<ButtonInput<KeyCode>>,
     time: Res<Time<Virtual>>,
+    config: Res<Config>,
+    sim_specs_query: Query<&SimulationSpecs>,
     mut exit_events: ResMut<Events<AppExit>>,
 ) {
     // info!("Waiting for plot");
-    if time.is_paused() && keyboard.just_pressed(KeyCode::KeyC) || time.elapsed_secs() > MAX_TIME {
+    if time.is_paused() && keyboard.just_pressed(KeyCode::KeyC)
+        || time.elapsed_secs() > config.simulation.max_time
+    {
         info!("Plotting");
-        let data = data_query.single().unwrap();
-
-        plot_data_temperature(
-            &data.time,
-            &data.southern_sample_temp,
-            &data.northern_sample_temp,
-            &data.max_temp,
-            &data.min_temp,
-            &data.avg_temp,
-            "Time [Ti.U.]",
-            "Temperature [Te.U.]",
-            "Temperature with regards to time",
-            "temperature.png",
-        )
-        .unwrap();
+        let plot = sim_specs_query.single().unwrap().plot.as_ref().unwrap();
+        let several = data_query.iter().len() > 1;
+        for (data, name) in &data_query {
+            let plot = if several {
+                per_planet(plot, name)
+            } else {
+                plot.clone()
+            };
+            data.plot(&plot.to_string_lossy()).unwrap();
+        }
         exit_events.send(AppExit::Success);
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn plot_data_temperature(
-    t_vals: &[f32],
-    southern_vals: &[f32],
-    northern_vals: &[f32],
-    max_vals: &[f32],
-    min_vals: &[f32],
-    avg_vals: &[f32],
+    t_vals: &[Float],
+    southern_vals: &[Float],
+    northern_vals: &[Float],
+    max_vals: &[Float],
+    min_vals: &[Float],
+    avg_vals: &[Float],
+    atmosphere_vals: &[Float],
     x_name: &str,
     y_name: &str,
     title: &str,
@@ -97,14 +180,29 @@ fn plot_data_temperature(
     root.fill(&WHITE)?;
 
     // Compute data range
-    let x_min = t_vals.iter().cloned().fold(f32::INFINITY, f32::min);
-    let x_max = t_vals.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
-    let y1_min = max_vals.iter().cloned().fold(f32::INFINITY, f32::min);
-    let y1_max = max_vals.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
-    let y2_min = min_vals.iter().cloned().fold(f32::INFINITY, f32::min);
-    let y2_max = min_vals.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
-    let y_min = y1_min.min(y2_min);
-    let y_max = y1_max.max(y2_max);
+    let x_min = t_vals.iter().cloned().fold(Float::INFINITY, Float::min);
+    let x_max = t_vals.iter().cloned().fold(Float::NEG_INFINITY, Float::max);
+    let y1_min = max_vals.iter().cloned().fold(Float::INFINITY, Float::min);
+    let y1_max = max_vals
+        .iter()
+        .cloned()
+        .fold(Float::NEG_INFINITY, Float::max);
+    let y2_min = min_vals.iter().cloned().fold(Float::INFINITY, Float::min);
+    let y2_max = min_vals
+        .iter()
+        .cloned()
+        .fold(Float::NEG_INFINITY, Float::max);
+    // The atmosphere can be colder than any point on the surface
+    let y3_min = atmosphere_vals
+        .iter()
+        .cloned()
+        .fold(Float::INFINITY, Float::min);
+    let y3_max = atmosphere_vals
+        .iter()
+        .cloned()
+        .fold(Float::NEG_INFINITY, Float::max);
+    let y_min = y1_min.min(y2_min).min(y3_min);
+    let y_max = y1_max.max(y2_max).max(y3_max);
 
     let mut chart = ChartBuilder::on(&root)
         .caption(
@@ -122,8 +220,8 @@ fn plot_data_temperature(
     chart
         .configure_mesh()
         .disable_mesh()
-        .axis_style(&BLACK)
-        .bold_line_style(&BLACK.mix(0.2))
+        .axis_style(BLACK)
+        .bold_line_style(BLACK.mix(0.2))
         .label_style(("monospace", 20).into_font().color(&BLACK))
         .x_desc(x_name)
         .y_desc(y_name)
@@ -179,6 +277,18 @@ fn plot_data_temperature(
         .label("Min Temperature")
         .legend(|(x, y)| Rectangle::new([(x - 15, y + 1), (x, y)], RGBColor(0, 0, 255)));
 
+    if !atmosphere_vals.is_empty() {
+        chart
+            .draw_series(
+                t_vals
+                    .iter()
+                    .zip(atmosphere_vals.iter())
+                    .map(|(&x, &y)| Circle::new((x, y), 2, RGBColor(0, 200, 200).filled())),
+            )?
+            .label("Average Atmosphere Temperature")
+            .legend(|(x, y)| Rectangle::new([(x - 15, y + 1), (x, y)], RGBColor(0, 200, 200)));
+    }
+
     chart
         .configure_series_labels()
         .position(SeriesLabelPosition::MiddleRight)
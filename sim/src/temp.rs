@@ -1,136 +1,71 @@
-use std::f32::{INFINITY, consts::PI};
-
-use crate::{
-    consts::*,
-    planet::{Planet, PlanetRenderTexture},
-    rk4::heat_eq_step_spherical,
-};
+use crate::planet::{Moon, Planet, PlanetRenderTexture, ShownLayer};
 use bevy::prelude::*;
-use ndarray::Array2;
-
-#[derive(Component)]
-// We use a vector because an array of this size would overflow the thread stack.
-pub struct TempMap(Array2<f32>); // T(x, y)
-impl TempMap {
-    pub fn new(init_temp: Array2<f32>) -> Self {
-        Self(init_temp)
-    }
-
-    pub fn apply_heat_eq(&mut self) {
-        self.0 = heat_eq_step_spherical(&self.0, DIFFUSION_DT);
-        let first_avg = self.0.column(0).iter().cloned().sum::<f32>() / WIDTH as f32;
-        self.0.column_mut(0).iter_mut().for_each(|t| *t = first_avg);
-
-        let last_avg = self.0.column(HEIGHT - 1).iter().cloned().sum::<f32>() / WIDTH as f32;
-        self.0
-            .column_mut(HEIGHT - 1)
-            .iter_mut()
-            .for_each(|t| *t = last_avg);
-    }
-
-    pub fn add_heat(&mut self, rhs: Array2<f32>) {
-        //self.0 = rhs;
-        self.0.scaled_add(1., &rhs);
-    }
-
-    // Helper functions that may be needed
-    // pub fn set_at(&mut self, x: usize, y: usize, t: f32) {
-    //     self.0[[x, y]] = t;
-    // }
-    // fn temp_at(&self, phi: f32, theta: f32) -> f32 {
-    //     self.0[(phi * WIDTH as f32) as usize][(theta * HEIGHT as f32) as usize]
-    // }
-
-    pub fn set_heat(&mut self, f: fn(f32, f32) -> f32) {
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                let temp = f(
-                    2. * PI * x as f32 / WIDTH as f32,
-                    PI * y as f32 / HEIGHT as f32,
-                );
-                self.0[[x, y]] = temp;
-            }
-        }
-    }
-
-    pub fn radiate_black_body(&mut self) {
-        for x in 0..WIDTH {
-            for y in 0..HEIGHT {
-                let t = self.0[[x, y]];
-                self.0[[x, y]] -= t.powi(4) * EPS * SIGMA * DA / C;
-            }
+use climate_core::{TempMap, float::narrow};
+
+// Texel (x, y) is grid cell (x, y): the UV sphere puts v = 0 at the same +z
+// pole as row 0 of the grid.
+pub fn get_heat_texture(temp: &TempMap) -> Vec<u8> {
+    let grid = temp.grid();
+    let mut colors = Vec::with_capacity(4 * grid.len());
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            let heat_color = heat_color(narrow(temp.field()[[x, y]]), 0., 200.);
+            colors.append(&mut heat_color.to_vec());
         }
     }
 
-    pub fn get_heat_texture(&self) -> Vec<u8> {
-        let mut colors = Vec::new();
+    colors
+}
 
-        for y in 0..HEIGHT {
-            for x in 0..WIDTH {
-                let heat_color = heat_color(self.0[[x, y]], 0., 200.);
-                colors.append(&mut heat_color.to_vec());
-            }
+pub fn apply_temp_image(
+    mut images: ResMut<Assets<Image>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    layer: Res<ShownLayer>,
+    planet_query: Query<(
+        &Planet,
+        &PlanetRenderTexture,
+        &MeshMaterial3d<StandardMaterial>,
+    )>,
+) {
+    for (planet, render_tex, planet_mesh) in &planet_query {
+        let image = images.get_mut(&render_tex.0).unwrap();
+        if let Some(ref mut data) = image.data {
+            // Planets without an atmosphere keep showing their surface
+            let temp = match *layer {
+                ShownLayer::Atmosphere => planet.0.atmosphere_temperature(),
+                ShownLayer::Surface => None,
+            };
+            let new_data = get_heat_texture(temp.unwrap_or(planet.0.temperature()));
+            *data = new_data;
         }
 
-        colors
-    }
-
-    pub fn get_heat_stats(&self) -> (f32, f32, f32, f32, f32) {
-        let mut max = -INFINITY;
-        let mut min = INFINITY;
-        let mut avg = 0.;
-
-        for t in &self.0 {
-            if *t > max {
-                max = *t;
-            }
-            if *t < min {
-                min = *t
-            }
-            avg += t
+        let mesh = materials.get_mut(&planet_mesh.0).unwrap();
+        if let Some(ref mut base_color_texture) = mesh.base_color_texture {
+            *base_color_texture = render_tex.0.clone();
         }
-
-        avg /= (HEIGHT * WIDTH) as f32;
-
-        (
-            max,
-            min,
-            avg,
-            self.0[[0, HEIGHT / 4]],
-            self.0[[0, HEIGHT * 3 / 4]],
-        )
-    }
-}
-
-pub fn apply_heat_eq(mut temp_query: Query<&mut TempMap>) {
-    let mut sim_steps = SPEEDUP;
-
-    let mut temp = temp_query.single_mut().unwrap();
-    while sim_steps >= 1 {
-        temp.apply_heat_eq();
-        sim_steps -= 1;
     }
 }
 
-pub fn apply_temp_image(
+pub fn apply_moon_images(
     mut images: ResMut<Assets<Image>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    render_tex: Res<PlanetRenderTexture>,
-    planet_query: Query<&mut MeshMaterial3d<StandardMaterial>, With<Planet>>,
-    temp_query: Query<&TempMap>,
+    planet_query: Query<&Planet>,
+    moon_query: Query<(&Moon, &MeshMaterial3d<StandardMaterial>)>,
 ) {
-    let temp = temp_query.single().unwrap();
-
-    let image = images.get_mut(&render_tex.0).unwrap();
-    if let Some(ref mut data) = image.data {
-        let new_data = temp.get_heat_texture();
-        *data = new_data;
-    }
+    for (moon, moon_mesh) in &moon_query {
+        let Ok(planet) = planet_query.get(moon.planet) else {
+            continue;
+        };
+        let image = images.get_mut(&moon.texture).unwrap();
+        if let Some(ref mut data) = image.data {
+            *data = get_heat_texture(planet.0.moon_temperature(moon.index));
+        }
 
-    let planet_mesh = planet_query.single().unwrap();
-    let mesh = materials.get_mut(&planet_mesh.0).unwrap();
-    if let Some(ref mut base_color_texture) = mesh.base_color_texture {
-        *base_color_texture = render_tex.0.clone();
+        let mesh = materials.get_mut(&moon_mesh.0).unwrap();
+        if let Some(ref mut base_color_texture) = mesh.base_color_texture {
+            *base_color_texture = moon.texture.clone();
+        }
     }
 }
 
@@ -151,5 +86,5 @@ fn heat_color(value: f32, min_temp: f32, max_temp: f32) -> [u8; 4] {
     let g = clamp(255.0 * (1.5 - (4.0 * t - 2.0).abs()), 0.0, 255.0) as u8;
     let b = clamp(255.0 * (1.5 - (4.0 * t - 1.0).abs()), 0.0, 255.0) as u8;
 
-    [r as u8, g as u8, b as u8, 255]
+    [r, g, b, 255]
 }
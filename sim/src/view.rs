@@ -1,4 +1,4 @@
-use std::f32::consts::PI;
+use std::path::PathBuf;
 
 use bevy::{
     asset::RenderAssetUsages,
@@ -6,12 +6,13 @@ use bevy::{
     render::render_resource::{Extent3d, TextureDimension, TextureFormat, TextureUsages},
 };
 use bevy_capture::{CameraTargetHeadless, CaptureBundle};
-use ndarray::arr2;
+use climate_core::{Grid, TempMap};
 
 use crate::{
-    consts::{HEIGHT, ROTATION_SPEED, TRANSLATION_SPEED, WIDTH},
-    planet::{Planet, PlanetRenderTexture},
-    temp::TempMap,
+    cli::Resolution,
+    consts::{ROTATION_SPEED, TRANSLATION_SPEED},
+    planet::{FocusedPlanet, Planet, PlanetRenderTexture},
+    temp::get_heat_texture,
 };
 
 #[derive(Component, Clone, Copy)]
@@ -71,9 +72,10 @@ pub fn update_camera(
     mut camera_query: Query<(&mut Transform, &mut ViewPoint), Without<Planet>>,
     keyboard: Res<ButtonInput<KeyCode>>,
     planet_query: Query<&Transform, With<Planet>>,
+    focus: Res<FocusedPlanet>,
 ) {
     let (mut camera_transform, view_point) = camera_query.single_mut().unwrap();
-    let planet_transform = planet_query.single().unwrap();
+    let planet_transform = planet_query.get(focus.0).unwrap();
 
     match view_point.into_inner() {
         ViewPoint::SolarSystem => {
@@ -154,9 +156,17 @@ fn set_view_free_cam(camera_transform: &mut Transform, keyboard: Res<ButtonInput
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct SimulationSpecs {
-    pub record: bool,
+    pub record: Option<Recording>,
+    /// Where to plot the sampled temperatures, if sampling
+    pub plot: Option<PathBuf>,
+}
+
+#[derive(Clone)]
+pub struct Recording {
+    pub output: PathBuf,
+    pub resolution: Resolution,
 }
 
 #[derive(Component)]
@@ -165,17 +175,23 @@ pub struct OverlayCamera2d;
 #[derive(Component)]
 struct OverlayRoot;
 
+/// The map of the focused planet in the overlay.
+#[derive(Component)]
+pub struct MapImage;
+
 // Spawn cameras
 pub fn setup_cameras(
     mut commands: Commands,
-    planet_rt: Res<PlanetRenderTexture>,
+    focus: Res<FocusedPlanet>,
+    planet_query: Query<&PlanetRenderTexture>,
     mut images: ResMut<Assets<Image>>,
     sim_specs_query: Query<&SimulationSpecs>,
 ) {
     let sim_specs = sim_specs_query.single().unwrap();
+    let resolution = sim_specs.record.as_ref().map(|record| record.resolution);
 
     // 3D camera
-    if sim_specs.record {
+    if let Some(resolution) = resolution {
         commands.spawn((
             Camera3d::default(),
             MainCam,
@@ -183,7 +199,7 @@ pub fn setup_cameras(
                 is_active: true,
                 ..default()
             }
-            .target_headless(3840, 2160, &mut images),
+            .target_headless(resolution.width, resolution.height, &mut images),
             CaptureBundle::default(),
             ViewPoint::Planet(5. * Vec3::X),
             solar_system_transform(),
@@ -206,13 +222,13 @@ pub fn setup_cameras(
         .spawn((
             Camera2d,
             MapCam,
-            if sim_specs.record {
+            if let Some(resolution) = resolution {
                 Camera {
                     order: 10,
                     is_active: false,
                     ..default()
                 }
-                .target_headless(3840, 2160, &mut images)
+                .target_headless(resolution.width, resolution.height, &mut images)
             } else {
                 Camera {
                     order: 10,
@@ -236,7 +252,8 @@ pub fn setup_cameras(
         ))
         .with_children(|parent| {
             parent.spawn((
-                ImageNode::new(planet_rt.0.clone()),
+                ImageNode::new(planet_query.get(focus.0).unwrap().0.clone()),
+                MapImage,
                 Node {
                     width: Val::Percent(100.0),
                     height: Val::Percent(100.0),
@@ -246,27 +263,39 @@ pub fn setup_cameras(
         });
 }
 
-pub fn setup_texture(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
-    let mut temp_map = TempMap::new(arr2(&[[0.; HEIGHT]; WIDTH]));
+/// Show the map of the planet in focus once the focus moves.
+pub fn update_map(
+    focus: Res<FocusedPlanet>,
+    planet_query: Query<&PlanetRenderTexture>,
+    mut map_query: Query<&mut ImageNode, With<MapImage>>,
+) {
+    if !focus.is_changed() {
+        return;
+    }
+    let Ok(render_tex) = planet_query.get(focus.0) else {
+        return;
+    };
+    for mut map in &mut map_query {
+        map.image = render_tex.0.clone();
+    }
+}
 
-    // temp_map.set_heat(|phi, _theta| if phi < PI { 200. } else { 0. });
-    temp_map.set_heat(|_theta, _phi| 0.);
+/// A blank temperature texture with one texel per cell of `grid`.
+pub fn heat_image(grid: &Grid) -> Image {
+    let temp_map = TempMap::zeros(grid);
     let mut img = Image::new_fill(
         Extent3d {
-            width: WIDTH as u32,
-            height: HEIGHT as u32,
+            width: grid.width as u32,
+            height: grid.height as u32,
             depth_or_array_layers: 1,
         },
         TextureDimension::D2,
-        &temp_map.get_heat_texture(),
+        &get_heat_texture(&temp_map),
         TextureFormat::Rgba8UnormSrgb,
         RenderAssetUsages::default(),
     );
 
     img.texture_descriptor.usage =
         TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST | TextureUsages::RENDER_ATTACHMENT;
-
-    commands.spawn(temp_map);
-    let rt_handle = images.add(img);
-    commands.insert_resource(PlanetRenderTexture(rt_handle));
+    img
 }